@@ -0,0 +1,410 @@
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use crate::fontlib::fontlib::{Font, FontFace};
+use crate::fontlib::error::FontError;
+use crate::fontlib::helper::{FileType, PGFFlags};
+
+/// Size and number of the texture pages glyphs are cached in, given when a font is loaded
+/// (see `Font::try_new_with_atlas`).
+///
+/// Pages are added when the glyphs do not fit in the pages that are already used, up to `pages`.
+/// Glyphs are only evicted once every page is full.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct AtlasConfig{
+    /// Width and height of a page in pixels: a power of two from 32 to 512 (the largest GU texture).
+    pub page_size: u32,
+    /// Largest number of pages (at least 1).
+    pub pages: usize,
+}
+
+impl AtlasConfig{
+    /// The atlas of the `CACHE_*` option of `options`: one 256x256 page (the default), one 512x512 page
+    /// (`CACHE_LARGE`), or up to four 512x512 pages (`CACHE_ALL`, which also pre-caches ASCII).
+    /// `CACHE_MED` is 0 like in intraFont, so it is only a name for the default and gets the same atlas.
+    pub fn from_options(options: PGFFlags) -> Self{
+        if options.contains(PGFFlags::CACHE_ALL){
+            AtlasConfig{ page_size: 512, pages: 4 }
+        } else if options.contains(PGFFlags::CACHE_LARGE){
+            AtlasConfig{ page_size: 512, pages: 1 }
+        } else {
+            AtlasConfig{ page_size: 256, pages: 1 }
+        }
+    }
+
+    pub(crate) fn validate(self) -> Result<Self, FontError>{
+        if self.page_size.is_power_of_two() && (32..=512).contains(&self.page_size) && self.pages > 0 && self.pages <= u8::MAX as usize{
+            Ok(self)
+        } else {
+            Err(FontError::InvalidAtlas { page_size: self.page_size, pages: self.pages })
+        }
+    }
+}
+
+impl Default for AtlasConfig{
+    fn default() -> Self{
+        AtlasConfig::from_options(PGFFlags::NONE)
+    }
+}
+
+/// Statistics of the glyph cache (the texture glyphs are drawn from) of a font face, see `Font::cache_stats`.
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub struct CacheStats{
+    /// Glyphs that were already in the texture when they were used.
+    pub hits: u32,
+    /// Glyphs that had to be decoded into the texture.
+    pub misses: u32,
+    /// Glyphs that were removed from the texture to make room for others.
+    pub evictions: u32,
+    /// Glyphs in the texture.
+    pub glyphs: usize,
+    /// Part of the pages in use taken by glyphs (0.0 to 1.0).
+    pub occupancy: f32,
+    /// Texture pages in use.
+    pub pages: usize,
+}
+
+/// Key of a glyph in the atlas: the id of a char glyph, or the id of a shadow glyph with the high bit set.
+pub(crate) type GlyphKey = u32;
+
+pub(crate) fn glyph_key(shadow: bool, id: usize) -> GlyphKey{
+    if shadow { id as u32 | 0x8000_0000 } else { id as u32 }
+}
+
+/// Whether `key` is the key of a shadow glyph, and the id of the glyph.
+pub(crate) fn split_key(key: GlyphKey) -> (bool, usize){
+    (key & 0x8000_0000 != 0, (key & 0x7FFF_FFFF) as usize)
+}
+
+/// A row of a page of the atlas: glyphs are put side by side in it, from the left.
+struct Shelf{
+    page: u8,
+    y: u16,
+    /// Height of the row, gap included.
+    height: u16,
+    /// Where the free space at the right of the row starts.
+    end: u16,
+}
+
+/// Space of a glyph in a shelf (gap included), free if `key` is `None`.
+struct Slot{
+    key: Option<GlyphKey>,
+    shelf: usize,
+    x: u16,
+    width: u16,
+    /// When the glyph was last used (see `Atlas::begin_text`), `PINNED` if it is never evicted.
+    last_used: u32,
+}
+
+/// `Slot::last_used` of the glyphs cached by `FontFace::precache`.
+const PINNED: u32 = u32::MAX;
+
+/// Where `Atlas::allocate` put a glyph.
+pub(crate) struct Allocation{
+    pub(crate) page: u8,
+    pub(crate) x: u16,
+    pub(crate) y: u16,
+    /// Size of the space that was given (gap included), which has to be cleared before the glyph is drawn.
+    pub(crate) width: u16,
+    pub(crate) height: u16,
+    /// Glyphs that were removed from the texture to make room.
+    pub(crate) evicted: Vec<GlyphKey>,
+}
+
+/// Places glyphs in texture pages, in rows (shelves). A page is added when the glyph does not fit in the others,
+/// and when every page is full, the least recently used glyphs are evicted.
+///
+/// Glyphs used since the current frame started (`begin_frame`) are never evicted. As long as `begin_frame` is
+/// not called, every text is a frame: only the glyphs of the text that is being cached are kept.
+pub(crate) struct Atlas{
+    /// Width and height of a page.
+    size: u16,
+    max_pages: u8,
+    pages: u8,
+    /// Height of new shelves (gap excluded): glyphs of the same line of text usually fit in one shelf.
+    pub(crate) row_height: u16,
+    shelves: Vec<Shelf>,
+    slots: Vec<Slot>,
+    index: BTreeMap<GlyphKey, usize>,
+    tick: u32,
+    frame_start: u32,
+    frames: bool,
+    /// Set when a glyph did not fit (reset by `FontFace::precache`).
+    pub(crate) full: bool,
+    hits: u32,
+    misses: u32,
+    evictions: u32,
+}
+
+impl Atlas{
+    pub(crate) fn new(config: AtlasConfig) -> Self{
+        Atlas{
+            size: config.page_size as u16,
+            max_pages: config.pages as u8,
+            pages: 1,
+            row_height: 0,
+            shelves: Vec::new(),
+            slots: Vec::new(),
+            index: BTreeMap::new(),
+            tick: 0,
+            frame_start: 0,
+            frames: false,
+            full: false,
+            hits: 0,
+            misses: 0,
+            evictions: 0,
+        }
+    }
+
+    /// Starts caching the glyphs of a text (used to find the least recently used glyphs).
+    pub(crate) fn begin_text(&mut self){
+        self.tick += 1;
+        if !self.frames{
+            self.frame_start = self.tick;
+        }
+    }
+
+    /// Starts a frame: glyphs used from now on are kept in the texture until the next frame.
+    pub(crate) fn begin_frame(&mut self){
+        self.frames = true;
+        self.tick += 1;
+        self.frame_start = self.tick;
+    }
+
+    /// Marks a glyph that is in the texture as used.
+    pub(crate) fn hit(&mut self, key: GlyphKey){
+        self.hits += 1;
+        if let Some(slot) = self.index.get(&key){
+            if self.slots[*slot].last_used != PINNED{
+                self.slots[*slot].last_used = self.tick;
+            }
+        }
+    }
+
+    /// Makes sure the glyphs used since `begin_text` are never evicted.
+    pub(crate) fn pin_text(&mut self){
+        let tick = self.tick;
+        for slot in self.slots.iter_mut().filter(|slot| slot.key.is_some() && slot.last_used == tick){
+            slot.last_used = PINNED;
+        }
+    }
+
+    /// Whether a glyph is in the texture for good (see `pin_text`).
+    pub(crate) fn is_pinned(&self, key: GlyphKey) -> bool{
+        self.index.get(&key).map_or(false, |slot| self.slots[*slot].last_used == PINNED)
+    }
+
+    /// Finds room for a `width` x `height` glyph, evicting the least recently used glyphs if needed.
+    ///
+    /// Returns `None` (and sets `full`) if the glyph does not fit without evicting glyphs of the current frame.
+    pub(crate) fn allocate(&mut self, key: GlyphKey, width: u16, height: u16) -> Option<Allocation>{
+        self.misses += 1;
+        let (w, h) = (width + 1, height + 1); // empty gap to prevent interpolation artifacts from showing
+        let mut evicted = Vec::new();
+        let slot = match self.free_slot(w, h).or_else(|| self.append(w, h)){
+            Some(slot) => slot,
+            None => match self.least_recently_used(w, h){
+                Some(slot) => {
+                    evicted.extend(self.slots[slot].key.take());
+                    slot
+                },
+                None => {
+                    evicted = self.evict_shelf(h);
+                    match self.append(w, h){
+                        Some(slot) => slot,
+                        None => {
+                            self.full = true;
+                            return None
+                        }
+                    }
+                },
+            },
+        };
+        for key in evicted.iter(){
+            self.index.remove(key);
+        }
+        self.evictions += evicted.len() as u32;
+        self.slots[slot].key = Some(key);
+        self.slots[slot].last_used = self.tick;
+        self.index.insert(key, slot);
+        let shelf = &self.shelves[self.slots[slot].shelf];
+        Some(Allocation{ page: shelf.page, x: self.slots[slot].x, y: shelf.y, width: self.slots[slot].width, height: shelf.height, evicted })
+    }
+
+    /// Frees the space of a glyph that could not be drawn.
+    pub(crate) fn release(&mut self, key: GlyphKey){
+        if let Some(slot) = self.index.remove(&key){
+            self.slots[slot].key = None;
+        }
+    }
+
+    /// Keys of the glyphs that are in the texture, in increasing order.
+    pub(crate) fn keys(&self) -> impl Iterator<Item = GlyphKey> + '_{
+        self.index.keys().copied()
+    }
+
+    /// Width and height of a page.
+    pub(crate) fn page_size(&self) -> u32{
+        self.size as u32
+    }
+
+    /// Bottom of the lowest shelf of `page` (the page is not used below).
+    pub(crate) fn bottom(&self, page: u8) -> u16{
+        self.shelves.iter().filter(|shelf| shelf.page == page).last().map_or(1, |shelf| shelf.y + shelf.height)
+    }
+
+    pub(crate) fn stats(&self) -> CacheStats{
+        let used: u32 = self.index.values()
+            .map(|slot| self.slots[*slot].width as u32 * self.shelves[self.slots[*slot].shelf].height as u32)
+            .sum();
+        CacheStats{
+            hits: self.hits,
+            misses: self.misses,
+            evictions: self.evictions,
+            glyphs: self.index.len(),
+            occupancy: used as f32 / (self.size as f32 * self.size as f32 * self.pages as f32),
+            pages: self.pages as usize,
+        }
+    }
+
+    /// The smallest free slot a `w` x `h` glyph fits in (glyphs that were evicted leave them).
+    fn free_slot(&self, w: u16, h: u16) -> Option<usize>{
+        self.slots.iter().enumerate()
+            .filter(|(_, slot)| slot.key.is_none() && slot.width >= w && self.shelves[slot.shelf].height >= h)
+            .min_by_key(|(_, slot)| (self.shelves[slot.shelf].height, slot.width))
+            .map(|(n, _)| n)
+    }
+
+    /// A new slot at the end of the lowest shelf the glyph fits in, or in a new shelf (on a new page if needed).
+    fn append(&mut self, w: u16, h: u16) -> Option<usize>{
+        let size = self.size;
+        let shelf = match self.shelves.iter().enumerate()
+            .filter(|(_, shelf)| shelf.height >= h && shelf.end + w <= size)
+            .min_by_key(|(_, shelf)| (shelf.height, shelf.page))
+            .map(|(n, _)| n){
+            Some(shelf) => shelf,
+            None => {
+                if w + 1 > size || h + 1 > size{
+                    return None
+                }
+                let (page, y) = match (0..self.pages).find(|page| self.bottom(*page) + h <= size){
+                    Some(page) => (page, self.bottom(page)),
+                    None if self.pages < self.max_pages => {
+                        self.pages += 1;
+                        (self.pages - 1, 1)
+                    },
+                    None => return None,
+                };
+                let height = h.max(self.row_height + 1);
+                let height = if y + height <= size { height } else { h };
+                self.shelves.push(Shelf{ page, y, height, end: 1 });
+                self.shelves.len() - 1
+            },
+        };
+        let x = self.shelves[shelf].end;
+        self.shelves[shelf].end += w;
+        self.slots.push(Slot{ key: None, shelf, x, width: w, last_used: 0 });
+        Some(self.slots.len() - 1)
+    }
+
+    /// The least recently used glyph (not used in this frame) whose slot a `w` x `h` glyph fits in.
+    fn least_recently_used(&self, w: u16, h: u16) -> Option<usize>{
+        self.slots.iter().enumerate()
+            .filter(|(_, slot)| slot.key.is_some() && slot.last_used < self.frame_start)
+            .filter(|(_, slot)| slot.width >= w && self.shelves[slot.shelf].height >= h)
+            .min_by_key(|(_, slot)| slot.last_used)
+            .map(|(n, _)| n)
+    }
+
+    /// Empties the least recently used shelf that is at least `h` high and has no glyph of this frame,
+    /// and returns the glyphs it held.
+    fn evict_shelf(&mut self, h: u16) -> Vec<GlyphKey>{
+        let frame_start = self.frame_start;
+        let last_used = |shelf: usize| self.slots.iter()
+            .filter(|slot| slot.shelf == shelf && slot.key.is_some())
+            .map(|slot| slot.last_used)
+            .max()
+            .unwrap_or(0);
+        let shelf = match (0..self.shelves.len())
+            .filter(|shelf| self.shelves[*shelf].height >= h && last_used(*shelf) < frame_start)
+            .min_by_key(|shelf| last_used(*shelf)){
+            Some(shelf) => shelf,
+            None => return Vec::new(),
+        };
+        let evicted = self.slots.iter().filter(|slot| slot.shelf == shelf).filter_map(|slot| slot.key).collect();
+        self.slots.retain(|slot| slot.shelf != shelf);
+        self.shelves[shelf].end = 1;
+        self.index = self.slots.iter().enumerate().filter_map(|(n, slot)| slot.key.map(|key| (key, n))).collect();
+        evicted
+    }
+}
+
+impl<'a> FontFace<'a>{
+    /// Hits, misses, evictions and occupancy of the texture glyphs are cached in.
+    pub fn cache_stats(&self) -> CacheStats{
+        self.atlas.stats()
+    }
+
+    /// Starts a frame (for the fallback fonts too): the glyphs drawn until the next call stay in the texture.
+    ///
+    /// Without it, only the glyphs of the text being drawn are protected, and a long text can evict the glyphs
+    /// of a text drawn before it in the same frame.
+    pub fn begin_frame(&mut self){
+        self.atlas.begin_frame();
+        for face in self.fallbacks.chain.iter(){
+            if let Ok(mut face) = face.try_borrow_mut(){
+                face.atlas.begin_frame();
+            }
+        }
+    }
+
+    /// Starts caching the glyphs of a text (for the fallback fonts too), see `Atlas::begin_text`.
+    pub(crate) fn begin_text(&mut self){
+        self.atlas.begin_text();
+        for face in self.fallbacks.chain.iter(){
+            if let Ok(mut face) = face.try_borrow_mut(){
+                face.atlas.begin_text();
+            }
+        }
+    }
+
+    /// Caches glyph (or shadow glyph) `id` if it is not `cached` yet, or marks it as used.
+    ///
+    /// Returns whether the texture changed.
+    pub(crate) fn use_glyph(&mut self, id: usize, glyph_type: PGFFlags, cached: bool) -> bool{
+        if cached{
+            self.atlas.hit(glyph_key(glyph_type.contains(PGFFlags::SHADOWGLYPH), id));
+            false
+        } else {
+            self.get_bmp(id, glyph_type)
+        }
+    }
+
+    /// Marks a glyph that was evicted from the texture as not cached.
+    pub(crate) fn uncache(&mut self, key: GlyphKey){
+        let (shadow, id) = split_key(key);
+        if shadow{
+            if let Some(glyph) = self.shadow_glyphs.get_mut(id){
+                glyph.flags.remove(PGFFlags::CACHED);
+            }
+        } else if self.filetype == FileType::BWFON{
+            if let Some(glyph) = self.glyphs_bw.get_mut(id){
+                glyph.flags.remove(PGFFlags::CACHED);
+            }
+        } else if let Some(glyph) = self.glyphs.get_mut(id){
+            glyph.flags.remove(PGFFlags::CACHED);
+        }
+    }
+}
+
+impl<'a> Font<'a>{
+    /// Hits, misses, evictions and occupancy of the texture glyphs are cached in (shared by every handle on the face).
+    pub fn cache_stats(&self) -> CacheStats{
+        self.face().cache_stats()
+    }
+
+    /// Starts a frame: the glyphs drawn until the next call are never evicted from the texture
+    /// (see `FontFace::begin_frame`). `FontController` calls it every frame.
+    pub fn begin_frame(&mut self){
+        self.face_mut().begin_frame();
+    }
+}
@@ -0,0 +1,154 @@
+use alloc::vec::Vec;
+use core::mem::size_of;
+use crate::fontlib::error::FontError;
+pub use crate::fontlib::vertex::FontVertex;
+
+/// How the vertices given to `RenderBackend::draw` make up glyphs.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Primitive{
+    /// 2 vertices per glyph: up-left and down-right corners (used when the text is not rotated).
+    Sprites,
+    /// 6 vertices per glyph: two triangles (used for rotated text).
+    Triangles,
+}
+
+/// The 4 bit/pixel texture holding the cached glyphs of a font.
+pub struct TextureImage<'t>{
+    pub width: u32,
+    pub height: u32,
+    /// `true` if the texture is swizzled for the GU (pages filled by `Font::precache`).
+    pub swizzled: bool,
+    /// 2 pixels per byte, low nibble first. Each pixel is an index in the CLUT.
+    pub data: &'t [u8],
+}
+
+/// Everything a font needs from a renderer.
+///
+/// `GuBackend` (with the `psp` feature) draws with the GU, `RecordingBackend` only remembers what it is asked to do.
+/// Other renderers can be plugged in by implementing this trait and printing with `Font::print_to`.
+///
+/// Vertices are in screen coordinates, with texture coordinates in texels of the font texture and
+/// colors in the same layout as `FontColor`.
+pub trait RenderBackend{
+    /// Loads the 16 colors of the color lookup table used by the font texture.
+    fn load_clut(&mut self, clut: &[u32; 16]);
+    /// Uses `texture` for everything that is drawn next.
+    fn upload_texture(&mut self, texture: &TextureImage);
+    /// Only draws inside of the rectangle from (x, y) to (right, bottom).
+    fn set_scissor(&mut self, x: i32, y: i32, right: i32, bottom: i32);
+    /// Draws on the whole screen again.
+    fn reset_scissor(&mut self);
+    /// Draws textured glyphs.
+    ///
+    /// `vertices` only live until this returns: backends that draw later have to copy them
+    /// (and return `FontError::OutOfVertexMemory` when they cannot).
+    fn draw(&mut self, primitive: Primitive, vertices: &[FontVertex]) -> Result<(), FontError>;
+}
+
+impl<B: RenderBackend + ?Sized> RenderBackend for &mut B{
+    fn load_clut(&mut self, clut: &[u32; 16]){
+        (**self).load_clut(clut)
+    }
+
+    fn upload_texture(&mut self, texture: &TextureImage){
+        (**self).upload_texture(texture)
+    }
+
+    fn set_scissor(&mut self, x: i32, y: i32, right: i32, bottom: i32){
+        (**self).set_scissor(x, y, right, bottom)
+    }
+
+    fn reset_scissor(&mut self){
+        (**self).reset_scissor()
+    }
+
+    fn draw(&mut self, primitive: Primitive, vertices: &[FontVertex]) -> Result<(), FontError>{
+        (**self).draw(primitive, vertices)
+    }
+}
+
+/// Bytes of display list the commands around every draw take (a jump to find where the list is, the draw itself
+/// and the state changes around it), with room left for the commands that close the list.
+pub(crate) const LIST_DRAW_RESERVE: usize = 64;
+
+/// Checks that a display list with `left` bytes left can hold `bytes` of vertices and the commands that draw them.
+///
+/// `sceGuGetMemory` rounds the size up to 4 bytes and adds a 2 word jump over the vertices.
+pub(crate) fn check_list_room(left: usize, bytes: usize) -> Result<(), FontError>{
+    let needed = ((bytes + 3) & !3) + 8 + LIST_DRAW_RESERVE;
+    if needed > left{
+        return Err(FontError::OutOfVertexMemory(bytes))
+    }
+    Ok(())
+}
+
+/// A command received by a `RecordingBackend`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RenderCommand{
+    LoadClut([u32; 16]),
+    UploadTexture { width: u32, height: u32, swizzled: bool },
+    SetScissor { x: i32, y: i32, right: i32, bottom: i32 },
+    ResetScissor,
+    Draw { primitive: Primitive, vertices: Vec<FontVertex> },
+}
+
+/// A backend that draws nothing and records every command, so printed text can be checked without a PSP.
+#[derive(Default)]
+pub struct RecordingBackend{
+    pub commands: Vec<RenderCommand>,
+    /// Number of vertices that can still be drawn (unlimited if `None`), like the memory left in a display list.
+    pub vertex_budget: Option<usize>,
+}
+
+impl RecordingBackend{
+    pub fn new() -> Self{
+        Self::default()
+    }
+
+    /// A backend that fails with `FontError::OutOfVertexMemory` once `vertices` vertices have been drawn.
+    pub fn with_vertex_budget(vertices: usize) -> Self{
+        Self { commands: Vec::new(), vertex_budget: Some(vertices) }
+    }
+
+    /// The primitive and vertices of every draw command, in order.
+    pub fn draws(&self) -> impl Iterator<Item = (Primitive, &[FontVertex])>{
+        self.commands.iter().filter_map(|command| match command{
+            RenderCommand::Draw { primitive, vertices } => Some((*primitive, &vertices[..])),
+            _ => None,
+        })
+    }
+
+    /// Forgets every recorded command.
+    pub fn clear(&mut self){
+        self.commands.clear();
+    }
+}
+
+impl RenderBackend for RecordingBackend{
+    fn load_clut(&mut self, clut: &[u32; 16]){
+        self.commands.push(RenderCommand::LoadClut(*clut));
+    }
+
+    fn upload_texture(&mut self, texture: &TextureImage){
+        self.commands.push(RenderCommand::UploadTexture { width: texture.width, height: texture.height, swizzled: texture.swizzled });
+    }
+
+    fn set_scissor(&mut self, x: i32, y: i32, right: i32, bottom: i32){
+        self.commands.push(RenderCommand::SetScissor { x, y, right, bottom });
+    }
+
+    fn reset_scissor(&mut self){
+        self.commands.push(RenderCommand::ResetScissor);
+    }
+
+    fn draw(&mut self, primitive: Primitive, vertices: &[FontVertex]) -> Result<(), FontError>{
+        if let Some(budget) = &mut self.vertex_budget{
+            if vertices.len() > *budget{
+                return Err(FontError::OutOfVertexMemory(vertices.len() * size_of::<FontVertex>()))
+            }
+            *budget -= vertices.len();
+        }
+        self.commands.push(RenderCommand::Draw { primitive, vertices: vertices.to_vec() });
+        Ok(())
+    }
+}
@@ -0,0 +1,85 @@
+use crate::fontlib::error::FontError;
+
+/// Reads values that are not byte-aligned from the tables of a font file.
+///
+/// Bits are read least significant bit first, which is how every bit-packed table in PGF files
+/// (charmap, char pointer table, glyph metrics and RLE bitmaps) is stored.
+/// Reading past the end of the data returns a `FontError` instead of panicking.
+#[derive(Copy, Clone)]
+pub struct BitReader<'d>{
+    data: &'d [u8],
+    position: usize, // in bits
+    table: &'static str, // used in errors
+}
+
+impl<'d> BitReader<'d>{
+
+    /// Creates a reader at the beginning of `data`. `table` is the name of the table used in errors.
+    pub fn new(data:&'d [u8], table:&'static str) -> Self{
+        Self { data, position: 0, table }
+    }
+
+    /// Creates a reader starting at the bit `position` of `data`.
+    pub fn at(data:&'d [u8], position:usize, table:&'static str) -> Result<Self, FontError>{
+        let mut reader = Self::new(data, table);
+        reader.seek(position)?;
+        Ok(reader)
+    }
+
+    /// Current position of the reader (in bits).
+    pub fn position(&self) -> usize{
+        self.position
+    }
+
+    /// Moves the reader to the bit `position`.
+    pub fn seek(&mut self, position:usize) -> Result<(), FontError>{
+        if position > self.data.len() * 8{
+            return Err(self.out_of_bounds(position))
+        }
+        self.position = position;
+        Ok(())
+    }
+
+    /// Skips `bits` bits.
+    pub fn skip(&mut self, bits:usize) -> Result<(), FontError>{
+        self.seek(self.position + bits)
+    }
+
+    /// Reads an unsigned value that is `bits` bits long (at most 32, `FontError::InvalidBitWidth` otherwise).
+    pub fn read_bits(&mut self, bits:u32) -> Result<u32, FontError>{
+        if bits > 32{
+            return Err(FontError::InvalidBitWidth { table: self.table, bits })
+        }
+        if bits == 0{
+            return Ok(0)
+        }
+        let end = self.position + bits as usize;
+        if end > self.data.len() * 8{
+            return Err(self.out_of_bounds(end))
+        }
+        let byte = self.position / 8;
+        let shift = self.position % 8; // at most 7 + 32 bits are needed, which fits in a u64
+        let word = match self.data.get(byte..byte + 8){
+            Some(bytes) => u64::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3], bytes[4], bytes[5], bytes[6], bytes[7]]),
+            None => { // near the end of the data, only some bytes are left
+                let mut word = 0u64;
+                for (i, b) in self.data[byte..].iter().enumerate(){
+                    word |= (*b as u64) << (i * 8);
+                }
+                word
+            }
+        };
+        self.position = end;
+        Ok(((word >> shift) & ((1u64 << bits) - 1)) as u32)
+    }
+
+    /// Reads a signed 7 bit value (used for the `left` and `top` bearings of glyphs).
+    pub fn read_i7(&mut self) -> Result<i8, FontError>{
+        let value = self.read_bits(7)? as i32;
+        Ok((if value >= 64 { value - 128 } else { value }) as i8)
+    }
+
+    fn out_of_bounds(&self, position:usize) -> FontError{
+        FontError::TableOutOfBounds { table: self.table, offset: position / 8 }
+    }
+}
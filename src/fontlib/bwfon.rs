@@ -0,0 +1,36 @@
+use crate::fontlib::glyph::Glyph;
+use crate::fontlib::helper::PGFFlags;
+
+/// Number of bytes used by every glyph bitmap in a BWFON file.
+pub const GLYPH_STRIDE:usize = 36;
+/// Width (in pixels) of every BWFON glyph.
+pub const GLYPH_WIDTH:u8 = 16;
+/// Height (in pixels) of every BWFON glyph. 16 pixels per row at 1 bit/pixel -> 18 rows in 36 bytes.
+pub const GLYPH_HEIGHT:u8 = 18;
+
+/// Size of the firmware `jpn0.bwfon` (28427 glyphs).
+pub const JPN0_SIZE:usize = 1023372;
+
+/// Returns `true` if `data` is the size of the firmware `jpn0.bwfon`.
+///
+/// BWFON files have no header or magic number, they are just glyphs packed one after the other,
+/// so the firmware file is recognized by its exact size (other BWFON files can be loaded with `FontFace::try_new_bwfon`).
+pub fn is_bwfon(data:&[u8]) -> bool{
+    data.len() == JPN0_SIZE
+}
+
+/// The metrics shared by every character glyph in a BWFON file.
+///
+/// BWFON files do not contain any shadows, so BWFON glyphs are drawn without one.
+pub fn char_glyph() -> Glyph{
+    Glyph{
+        width: GLYPH_WIDTH,
+        height: GLYPH_HEIGHT,
+        left: 0,
+        top: GLYPH_HEIGHT as i8 - 3, // baseline sits 3 pixels above the bottom of the cell
+        flags: PGFFlags::BMP_HORIZONTAL_ROWS,
+        shadow_id: 65535,
+        advance: GLYPH_WIDTH as i8 * 4,
+        ..Glyph::default()
+    }
+}
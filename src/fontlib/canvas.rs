@@ -0,0 +1,222 @@
+use alloc::vec::Vec;
+use crate::fontlib::fontlib::{Font, FontFace};
+use crate::fontlib::layout::{LayoutOptions, Placed};
+use crate::fontlib::helper::PGFFlags;
+use crate::fontlib::math::floorf;
+use crate::fontlib::raster::GlyphBitmap;
+use crate::fontlib::rotation::Rotation;
+use crate::fontlib::style::{FontColor, FontStyle};
+
+/// Pixel formats of a `Canvas`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PixelFormat{
+    /// 4 bytes per pixel: red, green, blue, alpha (same layout as `GU_PSM_8888` and `FontColor`).
+    Rgba8888,
+    /// 1 byte per pixel: alpha only.
+    A8,
+}
+
+impl PixelFormat{
+    pub fn bytes_per_pixel(&self) -> usize{
+        match self{
+            PixelFormat::Rgba8888 => 4,
+            PixelFormat::A8 => 1,
+        }
+    }
+}
+
+/// An image in memory that text can be drawn into with `Font::render_to_buffer`.
+///
+/// Pixels are stored row after row without padding. Glyphs are blended over what is already in the canvas,
+/// the same way the GU blends them over the frame buffer.
+pub struct Canvas{
+    width: u32,
+    height: u32,
+    format: PixelFormat,
+    data: Vec<u8>,
+}
+
+impl Canvas{
+    /// Creates a fully transparent canvas.
+    pub fn new(width: u32, height: u32, format: PixelFormat) -> Self{
+        Canvas{ width, height, format, data: vec![0u8; width as usize * height as usize * format.bytes_per_pixel()] }
+    }
+
+    pub fn width(&self) -> u32{
+        self.width
+    }
+
+    pub fn height(&self) -> u32{
+        self.height
+    }
+
+    pub fn format(&self) -> PixelFormat{
+        self.format
+    }
+
+    /// Raw pixels of the canvas.
+    pub fn data(&self) -> &[u8]{
+        &self.data
+    }
+
+    pub fn data_mut(&mut self) -> &mut [u8]{
+        &mut self.data
+    }
+
+    pub fn into_data(self) -> Vec<u8>{
+        self.data
+    }
+
+    /// Fills the whole canvas with `color` (only its alpha for `A8` canvases).
+    pub fn clear(&mut self, color: FontColor){
+        let bytes = color.bits().to_le_bytes();
+        match self.format{
+            PixelFormat::Rgba8888 => self.data.chunks_exact_mut(4).for_each(|pixel| pixel.copy_from_slice(&bytes)),
+            PixelFormat::A8 => self.data.iter_mut().for_each(|alpha| *alpha = bytes[3]),
+        }
+    }
+
+    /// Color of the pixel at (x, y), in the same layout as `FontColor` (`A8` pixels are white).
+    pub fn pixel(&self, x: u32, y: u32) -> Option<u32>{
+        if x >= self.width || y >= self.height{
+            return None
+        }
+        let i = (x + y * self.width) as usize;
+        Some(match self.format{
+            PixelFormat::Rgba8888 => u32::from_le_bytes([self.data[i * 4], self.data[i * 4 + 1], self.data[i * 4 + 2], self.data[i * 4 + 3]]),
+            PixelFormat::A8 => (self.data[i] as u32) << 24 | 0xFFFFFF,
+        })
+    }
+
+    /// Blends `color` over the pixel at (x, y), with its alpha multiplied by `coverage` (like `TextureEffect::Modulate`).
+    fn blend(&mut self, x: u32, y: u32, color: FontColor, coverage: f32){
+        let bytes = color.bits().to_le_bytes();
+        let alpha = bytes[3] as f32 / 255.0 * coverage;
+        if alpha <= 0.0{
+            return
+        }
+        let i = (x + y * self.width) as usize;
+        let over = |src: f32, dst: u8| (src * alpha + dst as f32 * (1.0 - alpha) + 0.5) as u8;
+        match self.format{
+            PixelFormat::Rgba8888 => {
+                let pixel = &mut self.data[i * 4..i * 4 + 4];
+                for c in 0..3{
+                    pixel[c] = over(bytes[c] as f32, pixel[c]);
+                }
+                pixel[3] = over(255.0, pixel[3]);
+            },
+            PixelFormat::A8 => self.data[i] = over(255.0, self.data[i]),
+        }
+    }
+
+    /// Draws `bitmap` stretched over `rect` (before rotation), sampled with bilinear filtering like the GU does.
+    fn draw_bitmap(&mut self, bitmap: &GlyphBitmap, rect: (f32, f32, f32, f32), color: FontColor, rotation: &Rotation, origin: (f32, f32), clip: Option<(f32, f32)>){
+        let (xl, yu, xr, yd) = rect;
+        if xr <= xl || yd <= yu{
+            return
+        }
+        // bounding box of the rotated rectangle
+        let corners = [(xl, yu), (xr, yu), (xr, yd), (xl, yd)];
+        let (mut min_x, mut min_y, mut max_x, mut max_y) = (f32::MAX, f32::MAX, f32::MIN, f32::MIN);
+        for corner in corners.iter(){
+            let (x, y) = rotation.apply(origin, *corner);
+            min_x = min_x.min(x);
+            min_y = min_y.min(y);
+            max_x = max_x.max(x);
+            max_y = max_y.max(y);
+        }
+        if let Some((clip_left, clip_right)) = clip{
+            min_x = min_x.max(clip_left);
+            max_x = max_x.min(clip_right);
+        }
+        let (x0, y0) = (floorf(min_x).max(0.0) as u32, floorf(min_y).max(0.0) as u32);
+        let (x1, y1) = ((max_x + 1.0).min(self.width as f32).max(0.0) as u32, (max_y + 1.0).min(self.height as f32).max(0.0) as u32);
+
+        let (scale_x, scale_y) = (bitmap.width as f32 / (xr - xl), bitmap.height as f32 / (yd - yu));
+        for y in y0..y1{
+            for x in x0..x1{
+                let (px, py) = (x as f32 + 0.5, y as f32 + 0.5); // center of the pixel
+                if let Some((clip_left, clip_right)) = clip{
+                    if px < clip_left || px > clip_right{
+                        continue;
+                    }
+                }
+                let (ux, uy) = if rotation.is_rotated { rotation.invert(origin, (px, py)) } else { (px, py) };
+                let (tx, ty) = ((ux - xl) * scale_x, (uy - yu) * scale_y);
+                if tx < 0.0 || ty < 0.0 || tx > bitmap.width as f32 || ty > bitmap.height as f32{
+                    continue;
+                }
+                let coverage = sample(bitmap, tx - 0.5, ty - 0.5);
+                self.blend(x, y, color, coverage);
+            }
+        }
+    }
+}
+
+/// Bilinear interpolation of the coverage of `bitmap` at (x, y) in texels (0.0 to 1.0, empty outside of the bitmap).
+fn sample(bitmap: &GlyphBitmap, x: f32, y: f32) -> f32{
+    let (fx, fy) = (floorf(x), floorf(y));
+    let (wx, wy) = (x - fx, y - fy);
+    let texel = |tx: f32, ty: f32| {
+        if tx < 0.0 || ty < 0.0{
+            0.0
+        } else {
+            bitmap.pixel(tx as u32, ty as u32) as f32 / 255.0
+        }
+    };
+    let top = texel(fx, fy) * (1.0 - wx) + texel(fx + 1.0, fy) * wx;
+    let bottom = texel(fx, fy + 1.0) * (1.0 - wx) + texel(fx + 1.0, fy + 1.0) * wx;
+    top * (1.0 - wy) + bottom * wy
+}
+
+impl<'a> Font<'a>{
+
+    /// Draws `text` into `canvas` without the GU, like `print` draws it on the screen.
+    ///
+    /// Everything comes from `style` (size, colors, angle and options), not from the style set with `set_style`.
+    /// Returns the x coordinate of the end of the text.
+    pub fn render_to_buffer(&self, canvas: &mut Canvas, x: f32, y: f32, text: &str, style: &FontStyle) -> f32{
+        self.face().render_column_to_buffer(canvas, x, y, 0.0, text, style)
+    }
+
+    /// Draws `text` into `canvas`, breaking lines so they fit in `column` pixels (like `print_column_ex`).
+    ///
+    /// Text that scrolls (`SCROLL_*` options) is clipped to the column, as on the first frame of the animation.
+    pub fn render_column_to_buffer(&self, canvas: &mut Canvas, x: f32, y: f32, column: f32, text: &str, style: &FontStyle) -> f32{
+        self.face().render_column_to_buffer(canvas, x, y, column, text, style)
+    }
+}
+
+impl<'a> FontFace<'a>{
+
+    /// Draws `text` into `canvas` with `style` (see `Font::render_to_buffer`).
+    pub fn render_to_buffer(&self, canvas: &mut Canvas, x: f32, y: f32, text: &str, style: &FontStyle) -> f32{
+        self.render_column_to_buffer(canvas, x, y, 0.0, text, style)
+    }
+
+    /// Draws `text` into `canvas` with `style`, in a column of `column` pixels (see `Font::render_column_to_buffer`).
+    pub fn render_column_to_buffer(&self, canvas: &mut Canvas, x: f32, y: f32, column: f32, text: &str, style: &FontStyle) -> f32{
+        let text: Vec<u16> = self.encode(text.as_bytes()).collect();
+        let rotation = Rotation::new(style.angle);
+        let clip = self.scroll_window(x, column, &text, style);
+        let options = LayoutOptions{ x, y, column, style: *style };
+
+        // shadows are drawn first, below every glyph
+        let mut end = x;
+        for glyph_type in [PGFFlags::SHADOWGLYPH, PGFFlags::CHAR_GLYPH].iter(){
+            if *glyph_type == PGFFlags::SHADOWGLYPH && style.shadow_color.bits() >> 24 == 0{
+                continue; // invisible shadows
+            }
+            end = self.walk_ucs2(&text, &options, &mut |placed| {
+                let placed = match placed{
+                    Placed::Glyph(placed) if placed.glyph_type == *glyph_type => placed,
+                    _ => return,
+                };
+                if let Some(bitmap) = placed.font.rasterize(&placed.glyph){
+                    canvas.draw_bitmap(&bitmap, placed.rect, placed.color, &rotation, (x, y), clip);
+                }
+            });
+        }
+        end
+    }
+}
@@ -1,5 +1,20 @@
+//! Character code conversion (like libccc in intraFont): converts text in the codepages of the `STRING_*` options
+//! from and to UCS2 and UTF-8.
+//!
+//! - `decode` and `encode` convert whole strings, `Decoder` decodes text that arrives in pieces.
+//! - `Codepages::decode_ucs2` gives the UCS2 codes the fonts are drawn with.
+//!
+//! Every codepage is a table (`CodepageTable`) of little-endian UCS2 values, so other codepages can be decoded
+//! with tables built at runtime (from a file for example). The built-in tables were generated from the Windows
+//! mappings of the same codepages.
+
+use alloc::vec::Vec;
+use alloc::string::String;
+use core::fmt;
+use crate::fontlib::helper::PGFFlags;
+
 bitflags!{
-    pub(crate) struct Codepages: u32 {
+    pub struct Codepages: u32 {
         const ASCII     = 0x00;
         const US        = 0x01;
         const MLATIN    = 0x05;
@@ -15,3 +30,383 @@ bitflags!{
     }
 }
 
+/// UCS2 code given to bytes that do not map to any char.
+pub const UNDEFINED_CHAR: u16 = '?' as u16;
+
+/// Decoding table of a codepage. Bytes below 0x80 are always ASCII.
+#[derive(Debug, Copy, Clone)]
+pub struct CodepageTable<'t>{
+    /// UCS2 codes of the bytes 0x80 to 0xFF (128 little-endian u16, 0 if the byte is not a char on its own).
+    pub high: Option<&'t [u8]>,
+    /// Chars made of a lead byte and a trail byte (for Shift-JIS, GBK, Korean and Big5).
+    pub double: Option<DoubleByteTable<'t>>,
+}
+
+/// Double-byte part of a `CodepageTable`.
+#[derive(Debug, Copy, Clone)]
+pub struct DoubleByteTable<'t>{
+    /// First and last lead bytes.
+    pub leads: (u8, u8),
+    /// First and last trail bytes.
+    pub trails: (u8, u8),
+    /// A little-endian u16 for every (lead, trail) pair, row after row (0 if the pair is not a char).
+    pub data: &'t [u8],
+}
+
+impl<'t> DoubleByteTable<'t>{
+    fn get(&self, lead: u8, trail: u8) -> Option<u16>{
+        if lead < self.leads.0 || lead > self.leads.1 || trail < self.trails.0 || trail > self.trails.1{
+            return None
+        }
+        let row_len = (self.trails.1 - self.trails.0) as usize + 1;
+        let index = (lead - self.leads.0) as usize * row_len + (trail - self.trails.0) as usize;
+        read_u16(self.data, index)
+    }
+}
+
+fn read_u16(data: &[u8], index: usize) -> Option<u16>{
+    match data.get(index * 2..index * 2 + 2){
+        Some(bytes) => Some(u16::from_le_bytes([bytes[0], bytes[1]])),
+        None => None,
+    }
+}
+
+impl<'t> CodepageTable<'t>{
+    /// Decodes the first char of `bytes`: returns its UCS2 code (`None` if the bytes are not a char)
+    /// and the number of bytes it is made of.
+    ///
+    /// A lead byte without a valid trail byte only uses 1 byte.
+    pub fn decode_char(&self, bytes: &[u8]) -> (Option<u16>, usize){
+        let byte = bytes[0];
+        if byte < 0x80{
+            return (Some(byte as u16), 1)
+        }
+        if let Some(ucs2) = self.single(byte){
+            return (Some(ucs2), 1)
+        }
+        if let (Some(double), Some(trail)) = (&self.double, bytes.get(1)){
+            match double.get(byte, *trail){
+                Some(0) => return (None, 2),
+                Some(ucs2) => return (Some(ucs2), 2),
+                None => {},
+            }
+        }
+        (None, 1)
+    }
+
+    /// Decodes `bytes` to UCS2 and adds the chars to `output` (`UNDEFINED_CHAR` for bytes that are not chars).
+    pub fn decode_into(&self, bytes: &[u8], output: &mut Vec<u16>){
+        let mut i = 0;
+        while i < bytes.len(){
+            let (ucs2, len) = self.decode_char(&bytes[i..]);
+            output.push(ucs2.unwrap_or(UNDEFINED_CHAR));
+            i += len;
+        }
+    }
+
+    /// UCS2 code of a byte above 0x7F that is a char on its own.
+    fn single(&self, byte: u8) -> Option<u16>{
+        self.high.and_then(|high| read_u16(high, (byte - 0x80) as usize)).filter(|ucs2| *ucs2 != 0)
+    }
+
+    /// `true` if `byte` starts a double-byte char.
+    fn is_lead(&self, byte: u8) -> bool{
+        match &self.double{
+            Some(double) => byte >= 0x80 && byte >= double.leads.0 && byte <= double.leads.1 && self.single(byte).is_none(),
+            None => false,
+        }
+    }
+
+    /// Every (UCS2, code) pair of the table, sorted by UCS2 code (codes above 0xFF are lead byte << 8 | trail byte).
+    /// When a char has several codes, the smallest one comes first.
+    fn reverse(&self) -> Vec<(u16, u16)>{
+        let mut pairs: Vec<(u16, u16)> = (0x80..=0xFFu16).filter_map(|byte| self.single(byte as u8).map(|ucs2| (ucs2, byte))).collect();
+        if let Some(double) = &self.double{
+            for lead in double.leads.0..=double.leads.1{
+                for trail in double.trails.0..=double.trails.1{
+                    match double.get(lead, trail){
+                        Some(0) | None => {},
+                        Some(ucs2) => pairs.push((ucs2, (lead as u16) << 8 | trail as u16)),
+                    }
+                }
+            }
+        }
+        pairs.sort_unstable();
+        pairs
+    }
+}
+
+impl Codepages{
+    /// Codepage of the `STRING_*` bits of font options.
+    pub fn from_options(options: PGFFlags) -> Codepages{
+        Codepages::from_bits_truncate((options & PGFFlags::STRING_MASK).bits() >> 16)
+    }
+
+    /// Built-in table of the codepage (`None` for `ASCII` and `UTF8`, which are decoded without one).
+    ///
+    /// - `US`: codepage 437, `MLATIN`: 850, `RUSSIAN`: 866, `CYRILLIC`: 1251, `LATIN2`: 1252 (same numbers as intraFont).
+    /// - `S_JIS`: codepage 932, `GBK`: 936, `KOREAN`: 949, `BIG5`: 950.
+    pub fn table(self) -> Option<CodepageTable<'static>>{
+        let single = |high: &'static [u8]| CodepageTable{ high: Some(high), double: None };
+        let double = |high: Option<&'static [u8]>, leads: (u8, u8), trails: (u8, u8), data: &'static [u8]| CodepageTable{ high, double: Some(DoubleByteTable{ leads, trails, data }) };
+        Some(match self{
+            Codepages::US => single(include_bytes!("ccclib/cp437.bin")),
+            Codepages::MLATIN => single(include_bytes!("ccclib/cp850.bin")),
+            Codepages::RUSSIAN => single(include_bytes!("ccclib/cp866.bin")),
+            Codepages::CYRILLIC => single(include_bytes!("ccclib/cp1251.bin")),
+            Codepages::LATIN2 => single(include_bytes!("ccclib/cp1252.bin")),
+            Codepages::S_JIS => double(Some(include_bytes!("ccclib/cp932_high.bin")), (0x81, 0xFC), (0x40, 0xFC), include_bytes!("ccclib/cp932.bin")),
+            Codepages::GBK => double(None, (0x81, 0xFE), (0x40, 0xFE), include_bytes!("ccclib/cp936.bin")),
+            Codepages::KOREAN => double(None, (0x81, 0xFE), (0x41, 0xFE), include_bytes!("ccclib/cp949.bin")),
+            Codepages::BIG5 => double(None, (0x81, 0xFE), (0x40, 0xFE), include_bytes!("ccclib/cp950.bin")),
+            _ => return None,
+        })
+    }
+
+    /// Decodes `bytes` in this codepage to UCS2.
+    ///
+    /// `ASCII` bytes are copied as they are (bytes above 0x7F are Latin-1 chars), `UTF8` is decoded
+    /// (chars outside of the BMP and invalid sequences give `UNDEFINED_CHAR`).
+    pub fn decode_ucs2(self, bytes: &[u8]) -> Vec<u16>{
+        let mut output = Vec::with_capacity(bytes.len());
+        match self.table(){
+            Some(table) => table.decode_into(bytes, &mut output),
+            None if self == Codepages::UTF8 => output.extend(Utf8Units::new(bytes, UNDEFINED_CHAR)),
+            None => output.extend(bytes.iter().map(|byte| *byte as u16)),
+        }
+        output
+    }
+}
+
+/// Iterator over the UCS2 units of UTF-8 text (returned by `Font::encode`).
+///
+/// - Chars outside of the BMP (emoji...) give the replacement char, or a surrogate pair with `with_surrogates`.
+/// - Invalid and truncated sequences give the replacement char.
+#[derive(Debug, Clone)]
+pub struct Utf8Units<'t>{
+    bytes: &'t [u8],
+    i: usize,
+    /// Start of the last char.
+    start: usize,
+    replacement: u16,
+    surrogates: bool,
+    /// Low surrogate of the last char.
+    low: Option<u16>,
+}
+
+impl<'t> Utf8Units<'t>{
+    pub fn new(bytes: &'t [u8], replacement: u16) -> Self{
+        Utf8Units{ bytes, i: 0, start: 0, replacement, surrogates: false, low: None }
+    }
+
+    /// Gives chars outside of the BMP as surrogate pairs instead of the replacement char.
+    pub fn with_surrogates(mut self, surrogates: bool) -> Self{
+        self.surrogates = surrogates;
+        self
+    }
+
+    /// Byte offset of the char the next unit belongs to.
+    pub fn offset(&self) -> usize{
+        match self.low{
+            Some(_) => self.start,
+            None => self.i,
+        }
+    }
+
+    /// Decodes the char at `self.i`: returns its code (`None` for an invalid sequence) and moves past it.
+    fn next_char(&mut self) -> Option<u32>{
+        let bytes = self.bytes;
+        let lead = bytes[self.i];
+        let (len, mut ch) = if lead & 0b1000_0000 == 0b0000_0000 {
+            (1, lead as u32)
+        } else if lead & 0b1110_0000 == 0b1100_0000 {
+            (2, (lead & 0b0001_1111) as u32) // 2 byte codepoint
+        } else if lead & 0b1111_0000 == 0b1110_0000 {
+            (3, (lead & 0b0000_1111) as u32) // 3 byte codepoint
+        } else if lead & 0b1111_1000 == 0b1111_0000 {
+            (4, (lead & 0b0000_0111) as u32) // 4 byte codepoint
+        } else {
+            self.i += 1; // continuation byte without a lead byte
+            return None
+        };
+        self.i += 1;
+        for _ in 1..len{
+            match bytes.get(self.i){
+                Some(byte) if byte & 0b1100_0000 == 0b1000_0000 => {
+                    ch = ch << 6 | (byte & 0b0011_1111) as u32;
+                    self.i += 1;
+                },
+                _ => return None, // truncated sequence
+            }
+        }
+        // overlong sequences, surrogates and codes above U+10FFFF are not chars
+        let min = [0, 0, 0x80, 0x800, 0x10000][len];
+        if ch < min || (0xD800..0xE000).contains(&ch) || ch > 0x10FFFF{
+            return None
+        }
+        Some(ch)
+    }
+}
+
+impl<'t> Iterator for Utf8Units<'t>{
+    type Item = u16;
+
+    fn next(&mut self) -> Option<u16>{
+        if let Some(low) = self.low.take(){
+            return Some(low)
+        }
+        self.start = self.i;
+        if self.i >= self.bytes.len(){
+            return None
+        }
+        Some(match self.next_char(){
+            Some(ch) if ch < 0x10000 => ch as u16,
+            Some(ch) if self.surrogates => {
+                let ch = ch - 0x10000;
+                self.low = Some(0xDC00 | (ch & 0x3FF) as u16);
+                0xD800 | (ch >> 10) as u16
+            },
+            _ => self.replacement,
+        })
+    }
+}
+
+/// Decodes `bytes` in `codepage` to a string. Bytes that are not chars give `char::REPLACEMENT_CHARACTER`.
+pub fn decode(codepage: Codepages, bytes: &[u8]) -> String{
+    let mut decoder = Decoder::new(codepage);
+    let mut output = String::with_capacity(bytes.len());
+    decoder.decode(bytes, &mut output);
+    decoder.finish(&mut output);
+    output
+}
+
+/// Encodes `text` in `codepage`, or returns the first char that does not exist in the codepage.
+pub fn encode(codepage: Codepages, text: &str) -> Result<Vec<u8>, Unmappable>{
+    Encoder::new(codepage).encode(text)
+}
+
+/// A char that does not exist in the codepage text was encoded in.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Unmappable{
+    pub ch: char,
+    /// Byte offset of the char in the text.
+    pub index: usize,
+}
+
+impl fmt::Display for Unmappable{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "PSP-FONT: {:?} (U+{:04X}) at {} does not exist in the codepage", self.ch, self.ch as u32, self.index)
+    }
+}
+
+/// Encodes text in a codepage.
+///
+/// Creating an encoder builds the reverse table of the codepage, so keep it around to encode several strings.
+pub struct Encoder{
+    codepage: Codepages,
+    reverse: Vec<(u16, u16)>,
+}
+
+impl Encoder{
+    pub fn new(codepage: Codepages) -> Self{
+        Encoder{ codepage, reverse: codepage.table().map(|table| table.reverse()).unwrap_or_default() }
+    }
+
+    pub fn encode(&self, text: &str) -> Result<Vec<u8>, Unmappable>{
+        if self.codepage == Codepages::UTF8{
+            return Ok(text.as_bytes().to_vec())
+        }
+        let table = self.codepage.table();
+        let mut output = Vec::with_capacity(text.len());
+        for (index, ch) in text.char_indices(){
+            let code = ch as u32;
+            if code < 0x80 || (table.is_none() && code <= 0xFF){
+                output.push(code as u8);
+                continue;
+            }
+            let found = if code <= 0xFFFF{
+                self.reverse.binary_search_by(|pair| pair.0.cmp(&(code as u16)).then(core::cmp::Ordering::Greater)).err()
+                    .and_then(|first| self.reverse.get(first)).filter(|pair| pair.0 == code as u16)
+            } else {
+                None
+            };
+            match found{
+                Some((_, byte)) if *byte <= 0xFF => output.push(*byte as u8),
+                Some((_, pair)) => output.extend_from_slice(&pair.to_be_bytes()),
+                None => return Err(Unmappable{ ch, index }),
+            }
+        }
+        Ok(output)
+    }
+}
+
+/// Decodes text in a codepage that arrives in pieces (from a file or the network): a char can be split between two pieces.
+pub struct Decoder{
+    codepage: Codepages,
+    table: Option<CodepageTable<'static>>,
+    /// Bytes at the end of the last piece that are the start of a char.
+    pending: Vec<u8>,
+}
+
+impl Decoder{
+    pub fn new(codepage: Codepages) -> Self{
+        Decoder{ codepage, table: codepage.table(), pending: Vec::new() }
+    }
+
+    pub fn codepage(&self) -> Codepages{
+        self.codepage
+    }
+
+    /// Decodes the next piece of text and adds its chars to `output`.
+    pub fn decode(&mut self, bytes: &[u8], output: &mut String){
+        let mut data = core::mem::take(&mut self.pending);
+        data.extend_from_slice(bytes);
+        let mut i = 0;
+        match self.table{
+            Some(table) => {
+                while i < data.len(){
+                    if i + 1 == data.len() && table.is_lead(data[i]){
+                        break; // the trail byte is in the next piece
+                    }
+                    let (ucs2, len) = table.decode_char(&data[i..]);
+                    output.push(ucs2.and_then(|ucs2| char::from_u32(ucs2 as u32)).unwrap_or(char::REPLACEMENT_CHARACTER));
+                    i += len;
+                }
+            },
+            None if self.codepage == Codepages::UTF8 => {
+                while i < data.len(){
+                    match core::str::from_utf8(&data[i..]){
+                        Ok(text) => {
+                            output.push_str(text);
+                            i = data.len();
+                        },
+                        Err(error) => {
+                            output.push_str(core::str::from_utf8(&data[i..i + error.valid_up_to()]).unwrap_or_default());
+                            i += error.valid_up_to();
+                            match error.error_len(){
+                                Some(len) => {
+                                    output.push(char::REPLACEMENT_CHARACTER);
+                                    i += len;
+                                },
+                                None => break, // the end of the char is in the next piece
+                            }
+                        },
+                    }
+                }
+            },
+            None => {
+                output.extend(data.iter().map(|byte| *byte as char));
+                i = data.len();
+            },
+        }
+        self.pending.extend_from_slice(&data[i..]);
+    }
+
+    /// Ends the text: a char that was not finished gives `char::REPLACEMENT_CHARACTER`.
+    pub fn finish(&mut self, output: &mut String){
+        if !self.pending.is_empty(){
+            output.push(char::REPLACEMENT_CHARACTER);
+            self.pending.clear();
+        }
+    }
+}
@@ -1,9 +1,9 @@
 use alloc::vec::Vec;
+use crate::fontlib::bit_reader::BitReader;
+use crate::fontlib::error::FontError;
 
 pub( crate) struct CharmapData {
-    pub(crate) charmap_compression_table: Vec<u16>,
-    pub(crate) charmap_compression_table_len: u8,
-    pub(crate) charmap: Vec<u16>,
+    pub(crate) lookup: CharLookup,
     pub(crate) char_ptr_table: Vec<u16>,
     pub(crate) shadow_charmap: Vec<u16>,
 }
@@ -11,24 +11,98 @@ pub( crate) struct CharmapData {
 impl CharmapData{
     /// Able to extract the character data from the charmap and char_pointer_table in a PGF font file.
     /// It gets the raw data and turns it into a usable vector.
-    pub fn extract_char_data(data:&[u8], bits_per_element:u32, number_of_elements:u32) -> Vec<u16>{
-        let mut map:Vec<u16> = Vec::with_capacity(data.len()); // Holds the information being extracted from data.
-        let mut elem_n = 0; // keeps track of the byte being processed
+    pub fn extract_char_data(data:&[u8], bits_per_element:u32, number_of_elements:u32, table:&'static str) -> Result<Vec<u16>, FontError>{
+        // Holds the information being extracted from data (the number of elements comes from the file, it is only trusted as far as the data goes).
+        let mut map:Vec<u16> = Vec::with_capacity((number_of_elements as usize).min(data.len() * 8 / bits_per_element.max(1) as usize));
+        let mut reader = BitReader::new(data, table);
         for _ in 0..number_of_elements{
-            map.push(Self::magical_table_function(bits_per_element, data, &mut elem_n) as u16)
+            map.push(reader.read_bits(bits_per_element)? as u16)
         }
-        map
+        Ok(map)
+    }
+}
+
+/// Glyph ids of all the UCS2 chars, precomputed from the charmap so that finding a char does not
+/// scan the compression table.
+///
+/// The ids are stored in pages of 256 chars, indexed by the high byte of the char. Pages without any char
+/// in the font all point to page 0, which is empty. When (almost) every page is used, one `u16` per char
+/// takes about as much memory, and the ids are stored directly.
+pub(crate) enum CharLookup{
+    Pages{ pages: Vec<u16>, ids: Vec<u16> },
+    Direct(Vec<u16>),
+}
+
+/// Id of the chars that are not in the font.
+pub(crate) const NO_CHAR: u16 = 65535;
+
+impl CharLookup{
+    /// Builds the lookup from a compression table (pairs of `(first UCS2 char, number of chars)`).
+    ///
+    /// The chars of the compression table are numbered in order; the numbers are glyph ids if `charmap` is
+    /// empty (BWFON), or indices in `charmap` otherwise (PGF).
+    pub fn new(compression_table: &[u16], charmap: &[u16]) -> Self{
+        // the pages in use are found first, so that the ids of all the chars are only allocated when they are stored directly
+        let mut pages = vec![0u16; 256];
+        let mut n_pages = 1u16;
+        CharLookup::for_each_id(compression_table, charmap, |ucs2, _| if pages[ucs2 >> 8] == 0{
+            pages[ucs2 >> 8] = n_pages;
+            n_pages += 1;
+        });
+        if n_pages as usize * 256 > 3 * 65536 / 4{
+            let mut direct = vec![NO_CHAR; 65536];
+            CharLookup::for_each_id(compression_table, charmap, |ucs2, id| direct[ucs2] = id);
+            return CharLookup::Direct(direct)
+        }
+        let mut ids = vec![NO_CHAR; n_pages as usize * 256];
+        CharLookup::for_each_id(compression_table, charmap, |ucs2, id| ids[(pages[ucs2 >> 8] as usize) << 8 | (ucs2 & 0xFF)] = id);
+        CharLookup::Pages{ pages, ids }
     }
 
-    /// I have no clue on as to what this function does. All I know is that it turns
-    /// raw data from a table in a PGF file to something that is readable.
-    /// Also, this is used in extracting glyphs from font_data.
-    pub fn magical_table_function(bits_per_element:u32, table:&[u8], current_bit:&mut u32) -> u32{
-        let mut v = 0;
-        for i in 0..bits_per_element as u64 {
-            v += ((((table[(*current_bit as usize) / 8] >> ((*current_bit) % 8)) & 1) as u64) << i) as u32;
-            *current_bit += 1;
+    /// Calls `f` with every char of the compression table that is in the font and its glyph id.
+    fn for_each_id(compression_table: &[u16], charmap: &[u16], mut f: impl FnMut(usize, u16)){
+        let mut index = 0usize;
+        for pair in compression_table.chunks_exact(2){
+            let (first, len) = (pair[0] as usize, pair[1] as usize);
+            for ucs2 in first..(first + len).min(65536){
+                let id = if charmap.is_empty(){
+                    index + ucs2 - first
+                } else {
+                    charmap.get(index + ucs2 - first).map_or(NO_CHAR as usize, |id| *id as usize)
+                };
+                if id < NO_CHAR as usize{
+                    f(ucs2, id as u16)
+                }
+            }
+            index += len;
         }
-        v
     }
-}
\ No newline at end of file
+
+    /// Returns the glyph id of `ucs2`, or `NO_CHAR` if it is not in the font.
+    #[inline]
+    pub fn get(&self, ucs2: u16) -> u16{
+        match self{
+            CharLookup::Pages{ pages, ids } => ids[(pages[(ucs2 >> 8) as usize] as usize) << 8 | (ucs2 & 0xFF) as usize],
+            CharLookup::Direct(ids) => ids[ucs2 as usize],
+        }
+    }
+
+    /// Finds the glyph id of `ucs2` by scanning the compression table, like fonts did before the lookup existed.
+    /// Only kept to check the lookup (and to compare their speed).
+    #[cfg(test)]
+    pub fn scan(compression_table: &[u16], charmap: &[u16], ucs2: u16) -> u16{
+        let mut id = 0usize;
+        for pair in compression_table.chunks_exact(2){
+            if ucs2 >= pair[0] && (ucs2 as usize) < pair[0] as usize + pair[1] as usize{
+                id += (ucs2 - pair[0]) as usize;
+                return if charmap.is_empty(){
+                    id.min(NO_CHAR as usize) as u16
+                } else {
+                    charmap.get(id).copied().unwrap_or(NO_CHAR)
+                }
+            }
+            id += pair[1] as usize;
+        }
+        NO_CHAR
+    }
+}
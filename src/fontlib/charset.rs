@@ -0,0 +1,35 @@
+//! Sets of chars to pre-cache with `Font::precache`.
+
+use core::ops::RangeInclusive;
+use crate::fontlib::ccclib::Codepages;
+use crate::fontlib::fallback;
+
+/// Printable ASCII chars (what `PGFFlags::CACHE_ASCII` pre-caches).
+pub fn ascii() -> RangeInclusive<char>{
+    ' '..='~'
+}
+
+/// Printable Latin-1 chars: ASCII, and the accented letters and symbols from U+00A0 to U+00FF.
+pub fn latin_1() -> impl Iterator<Item = char>{
+    ascii().chain('\u{A0}'..='\u{FF}')
+}
+
+/// Hiragana, katakana, and the Japanese punctuation (「」、。...).
+pub fn kana() -> impl Iterator<Item = char>{
+    fallback::CJK_SYMBOLS_AND_PUNCTUATION.chain(fallback::HIRAGANA).chain(fallback::KATAKANA)
+}
+
+/// Hangul jamo (the letters syllables are made of), in their own block and in the compatibility block.
+pub fn hangul_jamo() -> impl Iterator<Item = char>{
+    fallback::HANGUL_JAMO.chain(fallback::HANGUL_COMPATIBILITY_JAMO)
+}
+
+/// The 2965 level 1 kanji of JIS X 0208 (the most common ones, which cover nearly all jouyou kanji),
+/// in Shift-JIS order.
+pub fn common_kanji() -> impl Iterator<Item = char>{
+    let table = Codepages::S_JIS.table();
+    (0x889Fu16..=0x9872)
+        .filter(|code| (0x40..=0xFC).contains(&(code & 0xFF)) && code & 0xFF != 0x7F)
+        .filter_map(move |code| table.as_ref()?.decode_char(&code.to_be_bytes()).0)
+        .filter_map(|ucs2| core::char::from_u32(ucs2 as u32))
+}
@@ -0,0 +1,115 @@
+use core::cell::RefMut;
+use core::fmt;
+use crate::fontlib::fontlib::{Font, FontFace};
+use crate::fontlib::backend::RenderBackend;
+use crate::fontlib::ccclib::Utf8Units;
+use crate::fontlib::error::FontError;
+use crate::fontlib::helper::PGFFlags;
+use crate::fontlib::layout::{LayoutOptions, Placed, TextLayout};
+use crate::fontlib::rotation::Rotation;
+use crate::fontlib::style::FontStyle;
+
+/// Prints formatted text without building a `String` first: `write!(cursor, "HP {}/{}", hp, max)`.
+///
+/// Glyphs are laid out as the text is written (left aligned, `\n` starts a new line) and everything is drawn
+/// in one batch by `finish`, or when the cursor is dropped.
+///
+/// The face of the font is borrowed until then: other handles on it can not print in the meantime.
+pub struct TextCursor<'c, 'a, B: RenderBackend>{
+    face: RefMut<'c, FontFace<'a>>,
+    style: FontStyle,
+    rotation: Rotation,
+    backend: B,
+    /// Start of the lines.
+    left: f32,
+    x: f32,
+    y: f32,
+    /// Point the text is rotated around.
+    origin: (f32, f32),
+    /// Number of UCS2 units written so far.
+    written: usize,
+    layout: TextLayout,
+    finished: bool,
+}
+
+impl<'a> Font<'a>{
+
+    /// Creates a cursor that prints the text written to it with `backend`, starting at (x, y) like `print_to`.
+    ///
+    /// `backend` can also be a `&mut` reference to a backend that is used after the cursor.
+    pub fn cursor_to<B: RenderBackend>(&mut self, backend: B, x: f32, y: f32) -> TextCursor<'_, 'a, B>{
+        let (style, rotation) = (self.style(), self.rotation);
+        let mut face = self.face_mut();
+        face.begin_text(); // the glyphs written to the cursor stay in the texture until they are drawn
+        let mut layout = core::mem::take(&mut face.layout_buffer);
+        layout.glyphs.clear();
+        layout.lines.clear();
+        TextCursor{ face, style, rotation, backend, left: x, x, y, origin: (x, y), written: 0, layout, finished: false }
+    }
+}
+
+impl<'c, 'a, B: RenderBackend> TextCursor<'c, 'a, B>{
+
+    /// Position the next char will be written at.
+    pub fn position(&self) -> (f32, f32){
+        (self.x, self.y)
+    }
+
+    /// Lays out one UCS2 unit at the position of the cursor and moves the cursor after it.
+    fn put(&mut self, ucs2: u16){
+        if ucs2 == '\n' as u16{
+            self.x = self.left;
+            self.y += self.face.advance.1 as f32 * self.style.size * 0.25;
+        } else {
+            let text = [ucs2];
+            self.face.cache_ucs2(&text);
+            let mut style = self.style;
+            style.options.remove(PGFFlags::ALIGN_MASK);
+            let options = LayoutOptions{ x: self.x, y: self.y, column: 0.0, style };
+            let (glyphs, source_index) = (&mut self.layout.glyphs, self.written);
+            self.x = self.face.walk_ucs2(&text, &options, &mut |placed| {
+                match placed{
+                    Placed::Glyph(glyph) if glyph.is_cached() => {
+                        let mut glyph = glyph.positioned();
+                        glyph.source_index = source_index;
+                        glyphs.push(glyph);
+                    },
+                    _ => (), // lines, and glyphs that could not be cached
+                }
+            });
+        }
+        self.written += 1;
+    }
+
+    /// Draws everything that was written, and returns where the text ends.
+    pub fn finish(mut self) -> Result<f32, FontError>{
+        self.draw()
+    }
+
+    fn draw(&mut self) -> Result<f32, FontError>{
+        self.finished = true;
+        let mut vertices = core::mem::take(&mut self.face.font_vertices);
+        let drawn = if self.layout.glyphs.is_empty() { Ok(()) } else { self.face.draw_layout(&mut self.backend, &self.layout, self.origin, &mut vertices, &self.rotation) };
+        self.face.font_vertices = vertices; // kept for the next print
+        self.face.layout_buffer = core::mem::take(&mut self.layout);
+        drawn.map(|_| self.x)
+    }
+}
+
+impl<'c, 'a, B: RenderBackend> fmt::Write for TextCursor<'c, 'a, B>{
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let mut units = Utf8Units::new(s.as_bytes(), self.face.replacement_char).with_surrogates(self.face.utf8_surrogates);
+        while let Some(ucs2) = units.next(){
+            self.put(ucs2);
+        }
+        Ok(())
+    }
+}
+
+impl<'c, 'a, B: RenderBackend> Drop for TextCursor<'c, 'a, B>{
+    fn drop(&mut self){
+        if !self.finished{
+            let _ = self.draw(); // errors can not be returned from drop, use `finish` to get them
+        }
+    }
+}
@@ -0,0 +1,52 @@
+use core::fmt;
+
+/// Everything that can go wrong while loading a font file or drawing text.
+///
+/// Returned by `Font::try_new` and `PGFHeader::load_from_bytes`, so a broken or truncated
+/// font can be handled (error screen, fallback font...) instead of taking the whole program down,
+/// and by the `print*` functions when there is no memory left for vertices.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FontError{
+    /// The file is smaller than a PGF header.
+    HeaderTooShort,
+    /// The file does not start with a known magic number (holds the 4 bytes that were found).
+    BadMagic([u8;4]),
+    /// The PGF revision is not 2 or 3.
+    UnsupportedRevision(u32),
+    /// A table of the font file points past the end of the data.
+    /// - `table` is the name of the table that was being read.
+    /// - `offset` is the byte offset (or index) that was out of bounds.
+    TableOutOfBounds { table: &'static str, offset: usize },
+    /// A table of the font file has elements of a size that can not be read (`bits` is not from 1 to 32).
+    InvalidBitWidth { table: &'static str, bits: u32 },
+    /// A glyph metric contains flags that do not make any sense.
+    InvalidGlyphFlags,
+    /// The file is a BWFON font, which does not carry its own charmap. Use `Font::try_new_bwfon`.
+    MissingCharmap,
+    /// The backend could not get memory for the vertices of a string (holds the number of bytes that were requested).
+    /// With the GU, vertices are taken from the display list: use a bigger list or print less per frame.
+    OutOfVertexMemory(usize),
+    /// The texture pages of an `AtlasConfig` can not be used: the page size is not a power of two from 32 to 512,
+    /// or there are no pages (or more than 255).
+    InvalidAtlas { page_size: u32, pages: usize },
+    /// A texture can not be swizzled: its rows are not a multiple of 16 bytes long, its height is not a multiple
+    /// of 8, or a buffer is smaller than the texture.
+    InvalidTexture { width: u32, height: u32 },
+}
+
+impl fmt::Display for FontError{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FontError::HeaderTooShort => write!(f, "PSP-FONT: file is too short to hold a font header"),
+            FontError::BadMagic(magic) => write!(f, "PSP-FONT: unsupported font format -> {:?}", magic),
+            FontError::UnsupportedRevision(revision) => write!(f, "PSP-FONT: unsupported PGF revision {}", revision),
+            FontError::TableOutOfBounds { table, offset } => write!(f, "PSP-FONT: {} is out of bounds at {}", table, offset),
+            FontError::InvalidBitWidth { table, bits } => write!(f, "PSP-FONT: {} has elements of {} bits", table, bits),
+            FontError::InvalidGlyphFlags => write!(f, "PSP-FONT: glyph has invalid flags"),
+            FontError::MissingCharmap => write!(f, "PSP-FONT: BWFON files need a charmap to be loaded"),
+            FontError::OutOfVertexMemory(bytes) => write!(f, "PSP-FONT: no memory left for {} bytes of vertices", bytes),
+            FontError::InvalidAtlas { page_size, pages } => write!(f, "PSP-FONT: can not cache glyphs in {} pages of {}x{}", pages, page_size, page_size),
+            FontError::InvalidTexture { width, height } => write!(f, "PSP-FONT: can not swizzle a {}x{} texture", width, height),
+        }
+    }
+}
@@ -0,0 +1,93 @@
+//! Fonts that draw the chars missing from another font (see `Font::set_fallback`).
+
+use alloc::rc::Rc;
+use alloc::vec::Vec;
+use core::cell::RefCell;
+use core::ops::RangeInclusive;
+use crate::fontlib::fontlib::{Font, FontFace};
+
+// Unicode blocks that are usually routed to a firmware font with `Font::route_fallback`.
+pub const HANGUL_JAMO: RangeInclusive<char> = '\u{1100}'..='\u{11FF}';
+pub const CJK_SYMBOLS_AND_PUNCTUATION: RangeInclusive<char> = '\u{3000}'..='\u{303F}';
+pub const HIRAGANA: RangeInclusive<char> = '\u{3040}'..='\u{309F}';
+pub const KATAKANA: RangeInclusive<char> = '\u{30A0}'..='\u{30FF}';
+pub const HANGUL_COMPATIBILITY_JAMO: RangeInclusive<char> = '\u{3130}'..='\u{318F}';
+pub const CJK_UNIFIED_IDEOGRAPHS: RangeInclusive<char> = '\u{4E00}'..='\u{9FFF}';
+pub const HANGUL_SYLLABLES: RangeInclusive<char> = '\u{AC00}'..='\u{D7AF}';
+pub const HALFWIDTH_AND_FULLWIDTH_FORMS: RangeInclusive<char> = '\u{FF00}'..='\u{FFEF}';
+
+/// The fallback fonts of a face.
+#[derive(Default)]
+pub(crate) struct Fallbacks<'a>{
+    /// Faces tried in order for the chars missing from the face.
+    pub(crate) chain: Vec<Rc<RefCell<FontFace<'a>>>>,
+    /// (first char, last char, index in `chain`): chars of these blocks try their face first.
+    pub(crate) routes: Vec<(u16, u16, usize)>,
+}
+
+impl<'a> Fallbacks<'a>{
+    /// Index of `face` in the chain, added at its end if it is not there yet.
+    fn index_of(&mut self, face: &Rc<RefCell<FontFace<'a>>>) -> usize{
+        match self.chain.iter().position(|known| Rc::ptr_eq(known, face)){
+            Some(n) => n,
+            None => {
+                self.chain.push(face.clone());
+                self.chain.len() - 1
+            }
+        }
+    }
+}
+
+impl<'a> FontFace<'a>{
+    /// Index (in the chain) of the fallback font that draws `ucs2`, if it is missing from this face.
+    ///
+    /// Faces that are borrowed elsewhere (by a `TextCursor`...) are skipped.
+    pub(crate) fn fallback_for(&self, ucs2: u16) -> Option<usize>{
+        let has_char = |n: &usize| self.fallbacks.chain[*n].try_borrow().map_or(false, |face| face.get_char_id(ucs2) < face.n_chars);
+        let routed = self.fallbacks.routes.iter().rev()
+            .find(|(first, last, _)| *first <= ucs2 && ucs2 <= *last)
+            .map(|(_, _, n)| *n);
+        routed.filter(has_char).or_else(|| (0..self.fallbacks.chain.len()).find(has_char))
+    }
+
+    /// The run of chars from `start` (missing from this face) that one fallback font draws: its index in the chain
+    /// and the end of the run, which stops at `end`, at newlines and at spaces (where lines can be broken).
+    pub(crate) fn fallback_run(&self, text: &[u16], start: usize, end: usize) -> Option<(usize, usize)>{
+        let n = self.fallback_for(text[start])?;
+        let mut run_end = start + 1;
+        while run_end < end && text[run_end] != '\n' as u16 && text[run_end] != ' ' as u16
+            && self.get_char_id(text[run_end]) >= self.n_chars && self.fallback_for(text[run_end]) == Some(n){
+            run_end += 1;
+        }
+        Some((n, run_end))
+    }
+}
+
+impl<'a> Font<'a>{
+    /// Sets the fonts that draw the chars missing from this font, tried in order (for every handle on the face).
+    ///
+    /// Runs of chars drawn by the same fallback font are measured and drawn together, at the size and with the
+    /// colors of this font, and lines are broken around them like around the chars of this font.
+    /// Routes set with `route_fallback` are cleared. A font can not be its own fallback.
+    pub fn set_fallback(&mut self, chain: &[&Font<'a>]){
+        let mut fallbacks = Fallbacks::default();
+        for font in chain.iter().filter(|font| !Rc::ptr_eq(&font.face, &self.face)){
+            fallbacks.index_of(&font.face);
+        }
+        self.face_mut().fallbacks = fallbacks;
+    }
+
+    /// Makes the chars of `chars` (a Unicode block like `fallback::HANGUL_SYLLABLES`) that are missing from this
+    /// font try `font` before the rest of the fallback chain, which `font` is added to.
+    ///
+    /// The last route set for a char is used.
+    pub fn route_fallback(&mut self, chars: RangeInclusive<char>, font: &Font<'a>){
+        if Rc::ptr_eq(&font.face, &self.face) || *chars.start() > '\u{FFFF}'{
+            return // fonts only hold UCS2 chars
+        }
+        let (first, last) = (*chars.start() as u16, (*chars.end()).min('\u{FFFF}') as u16);
+        let mut face = self.face_mut();
+        let n = face.fallbacks.index_of(&font.face);
+        face.fallbacks.routes.push((first, last, n));
+    }
+}
@@ -0,0 +1,139 @@
+use alloc::rc::Rc;
+use core::cell::{Ref, RefCell, RefMut};
+use crate::fontlib::fontlib::{AtlasConfig, FontFace};
+use crate::fontlib::ccclib::Utf8Units;
+use crate::fontlib::error::FontError;
+use crate::fontlib::font_data::FontData;
+use crate::fontlib::helper::PGFFlags;
+use crate::fontlib::rotation::Rotation;
+use crate::fontlib::style::{FontColor, FontStyle};
+
+/// A font face and the style text is drawn with.
+///
+/// Handles are cheap to make: `with_style` (or `clone`) gives another handle on the same face, so one file is
+/// only loaded once and its glyphs are cached in one texture, whatever the number of styles it is drawn with.
+#[derive(Clone)]
+pub struct Font<'a>{
+    pub(crate) face: Rc<RefCell<FontFace<'a>>>,
+    pub(crate) style: FontStyle,
+    pub(crate) rotation: Rotation,
+}
+
+impl<'a> Font<'a>{
+    /// Loads a font from the data of a font file, with the default style (size 1, white, black shadow).
+    ///
+    /// Panics if the font file is invalid. Use `Font::try_new` to handle broken font files.
+    pub fn new<D: Into<FontData<'a>>>(data: D, options: PGFFlags) -> Font<'a>{
+        Font::from(FontFace::new(data, options))
+    }
+
+    /// Loads a font from the data of a font file, with the default style (see `FontFace::try_new`).
+    pub fn try_new<D: Into<FontData<'a>>>(data: D, options: PGFFlags) -> Result<Font<'a>, FontError>{
+        FontFace::try_new(data, options).map(Font::from)
+    }
+
+    /// Loads a font with the default style, caching its glyphs in the texture pages of `atlas`
+    /// (see `FontFace::try_new_with_atlas`).
+    pub fn try_new_with_atlas<D: Into<FontData<'a>>>(data: D, options: PGFFlags, atlas: AtlasConfig) -> Result<Font<'a>, FontError>{
+        FontFace::try_new_with_atlas(data, options, atlas).map(Font::from)
+    }
+
+    /// Loads a BWFON file, with the default style (see `FontFace::try_new_bwfon`).
+    pub fn try_new_bwfon<D: Into<FontData<'a>>>(data: D, charmap_compression_table: &[u16], options: PGFFlags) -> Result<Font<'a>, FontError>{
+        FontFace::try_new_bwfon(data, charmap_compression_table, options).map(Font::from)
+    }
+
+    /// Loads a BWFON file, with the default style, caching its glyphs in the texture pages of `atlas`.
+    pub fn try_new_bwfon_with_atlas<D: Into<FontData<'a>>>(data: D, charmap_compression_table: &[u16], options: PGFFlags, atlas: AtlasConfig) -> Result<Font<'a>, FontError>{
+        FontFace::try_new_bwfon_with_atlas(data, charmap_compression_table, options, atlas).map(Font::from)
+    }
+
+    /// A handle on a face that is already shared, drawing with `style`.
+    pub fn from_shared(face: Rc<RefCell<FontFace<'a>>>, style: FontStyle) -> Font<'a>{
+        let mut font = Font{ face, style, rotation: Rotation::default() };
+        font.set_style(style);
+        font
+    }
+
+    /// Another handle on the face of this font, drawing with `style`.
+    pub fn with_style(&self, style: FontStyle) -> Font<'a>{
+        let mut font = self.clone();
+        font.set_style(style);
+        font
+    }
+
+    /// The face of this font, shared with every handle made from it.
+    pub fn shared_face(&self) -> Rc<RefCell<FontFace<'a>>>{
+        self.face.clone()
+    }
+
+    /// Borrows the face of this font.
+    ///
+    /// Panics if it is mutably borrowed (by a `TextCursor` of a handle on the same face, for example).
+    pub fn face(&self) -> Ref<'_, FontFace<'a>>{
+        self.face.borrow()
+    }
+
+    /// Mutably borrows the face of this font (it is shared with every handle made from it).
+    pub fn face_mut(&self) -> RefMut<'_, FontFace<'a>>{
+        self.face.borrow_mut()
+    }
+
+    pub fn set_style(&mut self, style: FontStyle){
+        let tolerance =  0.0078125f32; // 1/(2^7)
+        let diff = if self.rotation.angle > style.angle { self.rotation.angle - style.angle } else { style.angle - self.rotation.angle };
+        if diff > tolerance{ // avoid recomputations
+            self.rotation = Rotation::new(style.angle);
+        }
+        let mut options = (style.options & PGFFlags::OPTIONS_MASK) | (style.options & PGFFlags::STRING_MASK) | (style.options & PGFFlags::CACHE_MASK);
+        if (options & PGFFlags::WIDTH_MASK).bits() == 0{
+            options.insert(PGFFlags::from_bits((self.face().advance.0 as u32/ 8) & PGFFlags::WIDTH_MASK.bits()).unwrap());
+        }
+        self.style = FontStyle{ options, ..style };
+    }
+
+    /// The style set with `set_style` (with the width of `WIDTH_FIX` filled in).
+    pub fn style(&self) -> FontStyle{
+        FontStyle{ angle: self.rotation.angle, ..self.style }
+    }
+
+    /// Decodes UTF-8 text to UCS2 units, the way `print` does (see `FontFace::encode`).
+    pub fn encode<'t>(&self, input: &'t [u8]) -> Utf8Units<'t>{
+        self.face().encode(input)
+    }
+
+    /// Sets the char given to invalid UTF-8 and to chars that do not fit in UCS2, for every handle on the face.
+    pub fn set_replacement_char(&mut self, ucs2: u16){
+        self.face_mut().set_replacement_char(ucs2);
+    }
+
+    /// Gives chars outside of the BMP as surrogate pairs, for every handle on the face.
+    pub fn set_utf8_surrogates(&mut self, surrogates: bool){
+        self.face_mut().set_utf8_surrogates(surrogates);
+    }
+
+    /// Caches the glyphs of `chars` (`charset::kana()`, a string's `chars()`...) for good, for every handle on
+    /// the face (see `FontFace::precache`).
+    ///
+    /// Returns `false` (and caches none of them) if they do not fit in the atlas.
+    pub fn precache<I: IntoIterator<Item = char>>(&mut self, chars: I) -> bool{
+        self.face_mut().precache(chars)
+    }
+
+    /// Frees the glyph data of the face once it is no longer needed (see `FontFace::drop_glyph_data`).
+    pub fn drop_glyph_data(&mut self) -> bool{
+        self.face_mut().drop_glyph_data()
+    }
+}
+
+impl<'a> From<FontFace<'a>> for Font<'a>{
+    /// A handle on `face` with the default style, and the options the face was loaded with.
+    fn from(face: FontFace<'a>) -> Self{
+        let options = face.options;
+        Font{
+            face: Rc::new(RefCell::new(face)),
+            style: FontStyle{ size: 1.0, color: FontColor::WHITE, shadow_color: FontColor::BLACK, angle: 0.0, options },
+            rotation: Rotation::default(),
+        }
+    }
+}
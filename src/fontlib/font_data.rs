@@ -0,0 +1,74 @@
+use alloc::boxed::Box;
+use alloc::rc::Rc;
+use alloc::vec::Vec;
+use core::ops::Deref;
+
+/// The data of a font file, borrowed or owned by the font.
+///
+/// Fonts loaded from borrowed data (`&[u8]`, `&Vec<u8>`) can not outlive it. Owned (`Vec<u8>`, `Box<[u8]>`) and
+/// shared (`Rc<[u8]>`) data gives a `Font<'static>`, which can be kept in long-lived game state, and lets
+/// `Font::drop_glyph_data` free the glyph bitmaps once they are all in the texture.
+pub enum FontData<'a>{
+    Borrowed(&'a [u8]),
+    Owned(Box<[u8]>),
+    Shared(Rc<[u8]>),
+}
+
+impl<'a> FontData<'a>{
+    /// Whether the font is the only one holding the data (so dropping it frees memory).
+    pub fn is_owned(&self) -> bool{
+        match self{
+            FontData::Borrowed(_) => false,
+            FontData::Owned(_) => true,
+            FontData::Shared(data) => Rc::strong_count(data) == 1,
+        }
+    }
+}
+
+impl<'a> Deref for FontData<'a>{
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8]{
+        match self{
+            FontData::Borrowed(data) => data,
+            FontData::Owned(data) => data,
+            FontData::Shared(data) => data,
+        }
+    }
+}
+
+impl<'a> From<&'a [u8]> for FontData<'a>{
+    fn from(data: &'a [u8]) -> Self{
+        FontData::Borrowed(data)
+    }
+}
+
+impl<'a> From<&'a Vec<u8>> for FontData<'a>{
+    fn from(data: &'a Vec<u8>) -> Self{
+        FontData::Borrowed(&data[..])
+    }
+}
+
+impl<'a, const N: usize> From<&'a [u8; N]> for FontData<'a>{
+    fn from(data: &'a [u8; N]) -> Self{
+        FontData::Borrowed(&data[..]) // for include_bytes!
+    }
+}
+
+impl From<Vec<u8>> for FontData<'static>{
+    fn from(data: Vec<u8>) -> Self{
+        FontData::Owned(data.into_boxed_slice())
+    }
+}
+
+impl From<Box<[u8]>> for FontData<'static>{
+    fn from(data: Box<[u8]>) -> Self{
+        FontData::Owned(data)
+    }
+}
+
+impl From<Rc<[u8]>> for FontData<'static>{
+    fn from(data: Rc<[u8]>) -> Self{
+        FontData::Shared(data)
+    }
+}
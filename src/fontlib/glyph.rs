@@ -1,7 +1,11 @@
+use alloc::vec::Vec;
+use core::cell::{Ref, RefCell};
 use crate::fontlib::helper::PGFFlags;
 
 #[derive(Copy, Clone)]
 pub( crate) struct Glyph{
+    /// Texture page the glyph is cached in.
+    pub(crate) page:u8,
     pub(crate) x:u16,
     pub(crate) y:u16,
     pub(crate) width:u8,
@@ -17,6 +21,7 @@ pub( crate) struct Glyph{
 impl Default for Glyph{
     fn default() -> Self {
         Glyph{
+            page: 0,
             x: 0,
             y: 0,
             width: 0,
@@ -30,8 +35,16 @@ impl Default for Glyph{
         }
     }
 }
+
+impl Glyph{
+    /// How far the glyph goes above and below the baseline (in pixels).
+    pub(crate) fn extents(&self) -> (f32, f32){
+        (self.top as f32, self.height as f32 - self.top as f32)
+    }
+}
 #[derive(Copy, Clone)]
 pub( crate) struct GlyphBW{
+    pub(crate) page:u8,
     pub(crate) x:u16,
     pub(crate) y:u16,
     pub(crate) flags: PGFFlags
@@ -40,9 +53,91 @@ pub( crate) struct GlyphBW{
 impl Default for GlyphBW{
     fn default() -> Self {
         GlyphBW{
+            page: 0,
             x: 0,
             y: 0,
             flags: PGFFlags::NONE,
         }
     }
-}
\ No newline at end of file
+}
+/// Metrics of the glyphs of a font, by glyph id.
+///
+/// Fonts decode the metrics of all their glyphs when they are loaded, except with `PGFFlags::LAZY_METRICS`:
+/// then metrics are decoded (by the font) the first time a glyph is looked up, and only the glyphs that were
+/// looked up are stored, plus 2 bytes per glyph id to find them.
+pub(crate) struct GlyphTable{
+    inner: RefCell<GlyphSlots>,
+}
+
+struct GlyphSlots{
+    /// Index of every glyph id in `glyphs` (or `NOT_DECODED`). Empty if every glyph is decoded, then glyph ids are indices in `glyphs`.
+    slots: Vec<u16>,
+    glyphs: Vec<Glyph>,
+}
+
+const NOT_DECODED: u16 = u16::MAX;
+
+impl GlyphTable{
+    /// A table of glyphs that are all decoded.
+    pub fn new(glyphs: Vec<Glyph>) -> Self{
+        GlyphTable{ inner: RefCell::new(GlyphSlots{ slots: Vec::new(), glyphs }) }
+    }
+
+    /// A table of `len` glyphs that are decoded later.
+    pub fn lazy(len: usize) -> Self{
+        GlyphTable{ inner: RefCell::new(GlyphSlots{ slots: vec![NOT_DECODED; len], glyphs: Vec::new() }) }
+    }
+
+    pub fn is_lazy(&self) -> bool{
+        !self.inner.borrow().slots.is_empty()
+    }
+
+    /// Number of glyph ids.
+    pub fn len(&self) -> usize{
+        let inner = self.inner.borrow();
+        if inner.slots.is_empty() { inner.glyphs.len() } else { inner.slots.len() }
+    }
+
+    /// Returns the glyph `id`, or `None` if it does not exist or was not decoded yet.
+    pub fn get(&self, id: usize) -> Option<Glyph>{
+        let inner = self.inner.borrow();
+        inner.index(id).map(|index| inner.glyphs[index])
+    }
+
+    pub fn get_mut(&mut self, id: usize) -> Option<&mut Glyph>{
+        let inner = self.inner.get_mut();
+        let index = inner.index(id)?;
+        inner.glyphs.get_mut(index)
+    }
+
+    /// Stores the decoded glyph `id` of a lazy table.
+    pub fn insert(&self, id: usize, glyph: Glyph){
+        let mut inner = self.inner.borrow_mut();
+        if inner.slots.get(id) == Some(&NOT_DECODED){
+            inner.slots[id] = inner.glyphs.len() as u16;
+            inner.glyphs.push(glyph);
+        }
+    }
+
+    /// The glyphs that were decoded (all of them, unless the table is lazy).
+    pub fn decoded(&self) -> Ref<'_, [Glyph]>{
+        Ref::map(self.inner.borrow(), |inner| &inner.glyphs[..])
+    }
+
+    pub fn decoded_mut(&mut self) -> &mut [Glyph]{
+        &mut self.inner.get_mut().glyphs[..]
+    }
+}
+
+impl GlyphSlots{
+    fn index(&self, id: usize) -> Option<usize>{
+        if self.slots.is_empty(){
+            if id < self.glyphs.len() { Some(id) } else { None }
+        } else {
+            match self.slots.get(id){
+                Some(&NOT_DECODED) | None => None,
+                Some(index) => Some(*index as usize),
+            }
+        }
+    }
+}
@@ -0,0 +1,284 @@
+//! Everything that draws fonts on the PSP with the GU (and needs the `psp` feature).
+
+use crate::fontlib::fontlib::Font;
+use crate::fontlib::error::FontError;
+use crate::fontlib::cursor::TextCursor;
+use crate::fontlib::font_data::FontData;
+use crate::fontlib::backend::{RenderBackend, Primitive, TextureImage, FontVertex, check_list_room};
+use crate::fontlib::style::FontColor;
+use crate::fontlib::helper::PGFFlags;
+use alloc::vec::Vec;
+use core::mem::size_of;
+use psp::sys::{sceGuGetMemory, sceGuScissor, sceKernelDcacheWritebackAll, sceGuClutMode, sceGuTexMode, sceGuEnable, sceGuTexImage, sceGuTexFunc, sceGuTexEnvColor, sceGuTexOffset, sceGuTexWrap, sceGuTexFilter, sceGuClutLoad, ClutPixelFormat, GuState, TexturePixelFormat, MipmapLevel, TextureEffect, TextureColorComponent, GuTexWrapMode, TextureFilter, sceKernelDcacheWritebackRange, sceGuDisable, sceGuDrawArray, GuPrimitive, VertexType, sceGuDebugPrint, sceGuInit, sceGuDebugFlush, sceIoWrite, sceGuStart, GuContextType, sceKernelCreateThread, sceKernelCreateCallback, SceKernelCallbackFunction, SceKernelThreadEntry, ThreadAttributes, SceKernelThreadOptParam, sceKernelRegisterExitCallback, sceKernelSleepThreadCB, sceKernelStartThread, SceUid, sceGumMatrixMode, MatrixMode, sceGumLoadIdentity, sceGumPerspective, sceGuClearColor, sceGuClearDepth, sceGuClear, ClearBuffer, sceGuDrawBuffer, sceGuDispBuffer, sceGuDepthBuffer, sceGuOffset, sceGuViewport, sceGuDepthRange, sceGuDepthFunc, DepthFunc, sceGuFrontFace, FrontFaceDirection, sceGuShadeModel, ShadingModel, sceGuBlendFunc, BlendOp, BlendFactor, sceGuFinish, sceGuSync, GuSyncMode, GuSyncBehavior, sceDisplayWaitVblankStart, sceGuDisplay, sceGuSwapBuffers};
+use psp::sys::{DisplayPixelFormat};
+use psp::Align16;
+use core::ffi::c_void;
+use psp::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use core::ops::{Deref, DerefMut};
+
+
+static mut CLUT: Align16<[u32;16]> = Align16([0u32;16]); // Color Lookup Table
+static mut LIST: Align16<[u32;0x40000]> = Align16([0u32;0x40000]); // Gu List
+static mut RUNNING:bool = false; // Callback
+
+pub struct FontController<'a>{
+    font: Font<'a>,
+}
+
+impl<'a> FontController<'a>{
+
+    /// Creates a FontController that contains a Font.
+    ///
+    /// A FontController is a wrapper used to easily set up a Font.
+    pub fn new<D: Into<FontData<'a>>>(data: D, options: PGFFlags) -> Self{
+        let font = Font::new(data, options);
+        Self::sce_init();
+        Self { font }
+    }
+
+    /// Creates a FontController that contains a Font, or returns why the font could not be loaded.
+    pub fn try_new<D: Into<FontData<'a>>>(data: D, options: PGFFlags) -> Result<Self, FontError>{
+        let font = Font::try_new(data, options)?;
+        Self::sce_init();
+        Ok(Self { font })
+    }
+
+    /// Runs draw each frame until it returns `false`.
+    pub fn run<T>(&mut self, mut draw: T)
+        where T: FnMut(&mut Font) -> bool,
+    {
+        loop {
+            unsafe {
+                // a new display list every frame: this also frees the vertices of the last frame (see `GuBackend`)
+                sceGuStart(GuContextType::Direct, &mut LIST.0 as *mut [u32; 0x40000] as *mut _);
+                sceGumMatrixMode(MatrixMode::Projection);
+                sceGumLoadIdentity();
+                sceGumPerspective(75.0, 16.0/9.0, 0.5, 1000.0);
+                sceGumMatrixMode(MatrixMode::View);
+                sceGumLoadIdentity();
+                sceGumMatrixMode(MatrixMode::Model);
+                sceGumLoadIdentity();
+
+                sceGuClearColor(FontColor::GRAY.bits());
+                sceGuClearDepth(0);
+                sceGuClear(ClearBuffer::COLOR_BUFFER_BIT | ClearBuffer::DEPTH_BUFFER_BIT);
+            }
+
+            self.font.begin_frame(); // glyphs drawn in this frame are not evicted by the next texts of the frame
+            let result = draw(&mut self.font);
+
+            unsafe {
+                // end drawing
+                sceGuFinish();
+                sceGuSync(GuSyncMode::Finish, GuSyncBehavior::Wait);
+
+                // swap buffers (waiting for vsync)
+                sceDisplayWaitVblankStart();
+                sceGuSwapBuffers();
+            }
+
+            if !result{
+                break; // if the drawing function returns false then stop the loop
+            }
+        }
+    }
+
+    extern "C" fn exit_callback(arg1: i32, arg2: i32, common: *mut c_void) -> i32{
+        unsafe {
+            RUNNING = false;
+        }
+        0
+    }
+
+
+    extern "C" fn callback_thread(args: usize, argp: *mut c_void) -> i32{
+        unsafe {
+            let func:SceKernelCallbackFunction = Self::exit_callback;
+            let cbid = sceKernelCreateCallback(b"Exit Callback".as_ptr(), func, core::ptr::null_mut() as *mut _);
+            sceKernelRegisterExitCallback(cbid);
+            sceKernelSleepThreadCB();
+        }
+        0
+    }
+
+    fn setup_callbacks() -> SceUid{
+        unsafe {
+            let func:SceKernelThreadEntry = Self::callback_thread;
+            let thid = sceKernelCreateThread(b"CallbackThread".as_ptr(), func, 0x11, 0xFA0, ThreadAttributes::USER, 0 as *mut _);
+            if thid.0 >= 0{
+                sceKernelStartThread(thid, 0, 0 as *mut _);
+            }
+            thid
+        }
+    }
+
+    /// A pre-baked initialization for sceGu*
+    pub fn sce_init(){
+        use psp::BUF_WIDTH;
+        Self::setup_callbacks();
+
+        // Init GU
+        unsafe {
+            sceGuInit();
+            sceGuStart(GuContextType::Direct, &mut LIST.0 as *mut [u32; 0x40000] as *mut _ );
+
+            sceGuDrawBuffer(DisplayPixelFormat::Psm8888, core::ptr::null_mut(), BUF_WIDTH as i32);
+            sceGuDispBuffer(SCREEN_WIDTH as i32, SCREEN_HEIGHT as i32, 0x88000 as *mut _, BUF_WIDTH as i32);
+            sceGuDepthBuffer(0x110000 as *mut _, psp::BUF_WIDTH as i32);
+
+            sceGuOffset(2048 - (SCREEN_WIDTH / 2), 2048 - (SCREEN_HEIGHT / 2));
+            sceGuViewport(2048, 2048, SCREEN_WIDTH as i32, SCREEN_HEIGHT as i32);
+            sceGuDepthRange(65535, 0);
+            sceGuScissor(0,0, SCREEN_WIDTH as i32, SCREEN_HEIGHT as i32);
+            sceGuEnable(GuState::ScissorTest);
+            sceGuDepthFunc(DepthFunc::GreaterOrEqual);
+            sceGuEnable(GuState::DepthTest);
+            sceGuFrontFace(FrontFaceDirection::Clockwise);
+            sceGuShadeModel(ShadingModel::Smooth);
+            sceGuEnable(GuState::CullFace);
+            sceGuEnable(GuState::ClipPlanes);
+            sceGuEnable(GuState::Blend);
+            sceGuBlendFunc(BlendOp::Add, BlendFactor::SrcAlpha, BlendFactor::OneMinusSrcAlpha, 0, 0);
+            sceGuFinish();
+            sceGuSync(GuSyncMode::Finish, GuSyncBehavior::Wait);
+
+            sceDisplayWaitVblankStart();
+            sceGuDisplay(true);
+        }
+    }
+}
+
+impl<'a> Deref for FontController<'a>{
+    type Target = Font<'a>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.font
+    }
+}
+
+impl<'a> DerefMut for FontController<'a>{
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.font
+    }
+}
+
+/// Draws fonts with the GU.
+///
+/// This is the backend used by `Font::print` and `Font::print_column_ex`.
+/// The GU must have been initialized (with `FontController::sce_init` or by the program) and a display list must be open.
+///
+/// Vertices are stored in the display list itself (with `sceGuGetMemory`), so they are freed when the next
+/// list is started (every frame in `FontController::run`). Printing fails with `FontError::OutOfVertexMemory`
+/// when the list is full: `sceGuGetMemory` does not check it, so the backend has to know where the list ends
+/// (the list of `FontController` by default, see `GuBackend::with_list`).
+#[derive(Copy, Clone)]
+pub struct GuBackend{
+    /// Address of the end of the display list.
+    list_end: usize,
+}
+
+impl GuBackend{
+    /// A backend drawing into the display list of `size` bytes at `list` (the one given to `sceGuStart`).
+    pub fn with_list(list: *const c_void, size: usize) -> Self{
+        GuBackend { list_end: list as usize + size }
+    }
+}
+
+impl Default for GuBackend{
+    /// A backend drawing into the display list of `FontController`.
+    fn default() -> Self{
+        unsafe { GuBackend::with_list(LIST.0.as_ptr() as *const c_void, size_of::<[u32; 0x40000]>()) }
+    }
+}
+
+impl RenderBackend for GuBackend{
+    fn load_clut(&mut self, clut: &[u32; 16]){
+        unsafe {
+            CLUT.0 = *clut; // the GE reads the CLUT later, it has to stay in memory
+            sceKernelDcacheWritebackRange(&CLUT.0 as *const u32 as *const c_void, size_of::<[u32; 16]>() as u32);
+            sceGuClutMode(ClutPixelFormat::Psm8888, 0, 255, 0);
+            sceGuClutLoad(2, &CLUT.0 as *const u32 as *const c_void);
+        }
+    }
+
+    fn upload_texture(&mut self, texture: &TextureImage){
+        unsafe {
+            sceKernelDcacheWritebackRange(texture.data.as_ptr() as *const c_void, texture.data.len() as u32);
+            sceGuEnable(GuState::Texture2D);
+            sceGuTexMode(TexturePixelFormat::PsmT4, 0, 0, if texture.swizzled { 1 } else { 0 });
+            sceGuTexImage(MipmapLevel::None, texture.width as i32, texture.height as i32, texture.width as i32, texture.data.as_ptr() as *const c_void);
+            sceGuTexFunc(TextureEffect::Modulate, TextureColorComponent::Rgba);
+            sceGuTexEnvColor(0x0);
+            sceGuTexOffset(0.0, 0.0);
+            sceGuTexWrap(GuTexWrapMode::Clamp, GuTexWrapMode::Clamp);
+            sceGuTexFilter(TextureFilter::Linear, TextureFilter::Linear);
+        }
+    }
+
+    fn set_scissor(&mut self, x: i32, y: i32, right: i32, bottom: i32){
+        unsafe {
+            sceGuScissor(x, y, right, bottom);
+            sceGuEnable(GuState::ScissorTest);
+        }
+    }
+
+    fn reset_scissor(&mut self){
+        unsafe { sceGuScissor(0, 0, SCREEN_WIDTH as i32, SCREEN_HEIGHT as i32) };
+    }
+
+    fn draw(&mut self, primitive: Primitive, vertices: &[FontVertex]) -> Result<(), FontError>{
+        if vertices.is_empty(){
+            return Ok(())
+        }
+        let bytes = vertices.len() * size_of::<FontVertex>();
+        // sceGuGetMemory(0) only adds an empty jump, and tells where the list is now
+        let current = unsafe { sceGuGetMemory(0) } as usize;
+        check_list_room(self.list_end.saturating_sub(current), bytes)?;
+        let v = unsafe { sceGuGetMemory(bytes as i32) } as *mut FontVertex;
+        unsafe {
+            core::ptr::copy_nonoverlapping(vertices.as_ptr(), v, vertices.len());
+            sceKernelDcacheWritebackRange(v as *mut _, bytes as u32); // SAKYA, mrneo240 <-- from C version Intrafont
+            sceGuDisable(GuState::DepthTest);
+            sceGuDrawArray(match primitive { Primitive::Triangles => GuPrimitive::Triangles, Primitive::Sprites => GuPrimitive::Sprites },
+                           VertexType::TEXTURE_32BITF | VertexType::COLOR_8888 | VertexType::VERTEX_32BITF | VertexType::TRANSFORM_2D,
+                           vertices.len() as i32,
+                           core::ptr::null(),
+                           v as *const c_void);
+            sceGuEnable(GuState::DepthTest);
+        }
+        Ok(())
+    }
+}
+
+impl<'a> Font<'a>{
+
+    /// Prints `text` on the screen with the GU, with (x, y) on the baseline of the first line.
+    pub fn print(&mut self, x: f32, y:f32, text: &str) -> Result<f32, FontError>{
+        self.print_to(&mut GuBackend::default(), x, y, text)
+    }
+
+    /// Prints `chars` on the screen with the GU (without allocating, see `print_chars_to`).
+    pub fn print_chars<I: IntoIterator<Item = char>>(&mut self, x: f32, y: f32, chars: I) -> Result<f32, FontError>{
+        self.print_chars_to(&mut GuBackend::default(), x, y, chars)
+    }
+
+    /// Prints UCS2 `text` (from system dialogs or savedata for example) on the screen with the GU.
+    pub fn print_ucs2(&mut self, x: f32, y: f32, text: &[u16]) -> Result<f32, FontError>{
+        self.print_ucs2_to(&mut GuBackend::default(), x, y, text)
+    }
+
+    /// Prints `text`, encoded in the codepage of the `STRING_*` option of the font, on the screen with the GU.
+    pub fn print_bytes(&mut self, x: f32, y: f32, text: &[u8]) -> Result<f32, FontError>{
+        self.print_bytes_to(&mut GuBackend::default(), x, y, text)
+    }
+
+    /// Prints the first `length` chars of `text` on the screen with the GU, in a column of `column` pixels.
+    pub fn print_column_ex(&mut self, x: f32, y: f32, column: f32, text: &str, length: i32) -> Result<f32, FontError>{
+        self.print_column_to(&mut GuBackend::default(), x, y, column, text, length)
+    }
+
+    /// Returns a cursor at (x, y) that prints what is written to it with the GU: `write!(font.cursor(x, y), "HP {}/{}", hp, max)`.
+    pub fn cursor(&mut self, x: f32, y: f32) -> TextCursor<'_, 'a, GuBackend>{
+        self.cursor_to(GuBackend::default(), x, y)
+    }
+}
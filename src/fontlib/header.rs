@@ -0,0 +1,117 @@
+use byteorder::{LittleEndian, ByteOrder};
+use crate::fontlib::error::FontError;
+
+/// An internal structure that is used when reading a bitmap font file
+/// Similar to the PGF_Header struct in intrafont, however, this structure is not
+/// loaded with the raw data from reading the file, it is safely read.
+pub struct PGFHeader{       // TYPICAL VALUES FOR THE DATA
+    pub(crate) header_start:u16,   // 0
+    pub(crate) header_len:u16,     // 392 or 412
+    pub(crate) pgf_id:[char;4],    // "PGF0"
+    pub(crate) revision:u32,       // 2 or 3
+    pub(crate) version:u32,        // 6
+    pub(crate) charmap_len:u32,    // MAX: 65536   (number of char-glyphs in fontdata)
+    pub(crate) charptr_len:u32,    // MAX: 512     (number of elements in char_pointer_table)
+    pub(crate) charmap_bpe:u32,    //              (number of bits per element in charmap)
+    pub(crate) charptr_bpe:u32,    //              (number of bits per element in char_pointer_table)
+    pub(crate) size:(u32,u32),     // (640, 640)   (point size in 1/64 points)
+    pub(crate) resolution:(u32,u32), // (8192, 8192) (resolution in 1/64 dpi)
+    pub(crate) family:[char;64],   // "Comic Sans" (the font name/family)
+    pub(crate) style:[char;64],    // "Bold"       (the font type/style)
+    pub(crate) charmap_min:u16,    //              (first element in charmap)
+    pub(crate) charmap_max:u16,    //              (last element in charmap)
+    pub(crate) max_top:u32,        //              (highest glyph top above the baseline, in 1/64 pixels)
+    pub(crate) advance:(u32,u32),  // (max x-advance, max y-advance)
+    pub(crate) max_size:(u32,u32), //              (largest glyph width and height, in 1/64 pixels)
+    pub(crate) dimension_table_len:u8,
+    pub(crate) adjust_table_len:(u8,u8), // (x-adjust-table-len, y-adjust-table-len)
+    pub(crate) advance_table_len:u8,
+    pub(crate) shadowmap_len:u32,  // MAX: 512     (number of elements in shadow_charmap (number of shadow-glyphs in fontdata))
+    pub(crate) shadowmap_bpe:u32,  // 16           (number of bits per element in shadow_charmap)
+    pub(crate) shadowscale:(u32, u32)  // (x-shadowscale,y-shadowscale)
+}
+
+#[feature(min_const_generics)]
+impl PGFHeader{
+    pub(crate) const HEADER_SIZE:usize = 0x184; // 388 bytes in length
+    pub fn load_from_bytes(file:&[u8]) -> Result<PGFHeader, FontError>{
+        if file.len() < Self::HEADER_SIZE{ // the file must be invalid if it not large enough for a header
+            return Err(FontError::HeaderTooShort)
+        }
+        let header_start= LittleEndian::read_u16(&file[0..=1]);
+        let header_len  = LittleEndian::read_u16(&file[0x2..=0x3]);
+        let mut pgf_id = {
+            let mut arr = [' ';4];
+            for i in 0..4{
+                arr[i] = file[0x4+i] as char;
+            }
+            arr
+        };
+        let revision    = LittleEndian::read_u32(&file[0x8..=0xB]);
+        let version     = LittleEndian::read_u32(&file[0xC..=0xF]);
+        let charmap_len = LittleEndian::read_u32(&file[0x10..=0x13]);
+        let charptr_len = LittleEndian::read_u32(&file[0x14..=0x17]);
+        let charmap_bpe = LittleEndian::read_u32(&file[0x18..=0x1B]);
+        let charptr_bpe = LittleEndian::read_u32(&file[0x1C..=0x1F]);
+        let size = (LittleEndian::read_u32(&file[0x24..=0x27]), LittleEndian::read_u32(&file[0x28..=0x2B]));
+        let resolution = (LittleEndian::read_u32(&file[0x2C..=0x2F]), LittleEndian::read_u32(&file[0x30..=0x33]));
+        let family = {
+            let mut arr = [' ';64];
+            for i in 0..64{
+                arr[i] = file[0x35+i] as char;
+            }
+            arr
+        };
+        let style = {
+            let mut arr = [' ';64];
+            for i in 0..64{
+                arr[i] = file[0x75+i] as char;
+            }
+            arr
+        };
+        let charmap_min = LittleEndian::read_u16(&file[0xB6..=0xB7]);
+        let charmap_max = LittleEndian::read_u16(&file[0xB8..=0xB9]);
+        let max_top = LittleEndian::read_u32(&file[0xE0..=0xE3]);
+        let advance = (LittleEndian::read_u32(&file[0xEC..=0xEF]), LittleEndian::read_u32(&file[0xF0..=0xF3]));
+        let max_size = (LittleEndian::read_u32(&file[0xF4..=0xF7]), LittleEndian::read_u32(&file[0xF8..=0xFB]));
+        let dimension_table_len = file[0x102];
+        let adjust_table_len = (file[0x103], file[0x104]);
+        let advance_table_len = file[0x105];
+        let shadowmap_len = LittleEndian::read_u32(&file[0x16C..=0x16F]);
+        let shadowmap_bpe = LittleEndian::read_u32(&file[0x170..=0x173]);
+        let shadowscale = (LittleEndian::read_u32(&file[0x178..=0x17B]), LittleEndian::read_u32(&file[0x17C..=0x17F]));
+        for (table, bits) in [("charmap", charmap_bpe), ("char pointer table", charptr_bpe)].iter(){
+            if *bits == 0 || *bits > 32{
+                return Err(FontError::InvalidBitWidth { table: *table, bits: *bits })
+            }
+        }
+
+        Ok(PGFHeader{ header_start, header_len, pgf_id, revision, version, charmap_len, charptr_len, charmap_bpe, charptr_bpe, size, resolution, family,
+            style, charmap_min, charmap_max, max_top, advance, max_size, dimension_table_len, adjust_table_len, advance_table_len, shadowmap_len, shadowmap_bpe, shadowscale
+        })
+    }
+
+    /// Highest and lowest a glyph goes above and below the baseline (in pixels), from the largest glyph top and size.
+    pub(crate) fn extents(&self) -> (f32, f32){
+        let top = self.max_top as i32 as f32 / 64.0;
+        (top.max(0.0), (self.max_size.1 as i32 as f32 / 64.0 - top).max(0.0))
+    }
+}
+
+/// Safely gets the bytes of a table in a font file.
+///
+/// Returns `FontError::TableOutOfBounds` if the table goes beyond the end of the file.
+pub(crate) fn read_table<'d>(data:&'d [u8], start:usize, end:usize, table:&'static str) -> Result<&'d [u8], FontError>{
+    data.get(start..end).ok_or(FontError::TableOutOfBounds { table, offset: start })
+}
+
+/// End of a table that starts at `start` and holds `len` elements of `bits` bits, padded to 32 bits.
+///
+/// Returns `FontError::TableOutOfBounds` if the end does not fit in a `usize` (it can not be in the file then).
+pub(crate) fn table_end(start:usize, len:u32, bits:usize, table:&'static str) -> Result<usize, FontError>{
+    (len as usize).checked_mul(bits)
+        .and_then(|bits| bits.checked_add(31))
+        .and_then(|bits| (bits / 32).checked_mul(4))
+        .and_then(|bytes| start.checked_add(bytes))
+        .ok_or(FontError::TableOutOfBounds { table, offset: start })
+}
@@ -1,12 +1,28 @@
 use crate::fontlib::ccclib::Codepages as CP;
+use crate::fontlib::bwfon;
 use alloc::alloc::{alloc, dealloc, Layout};
 use core::mem::size_of;
 
-#[derive(Eq, PartialEq)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub enum FileType{
     PGF,
     BWFON
 }
+
+impl FileType{
+    /// Guesses the type of a font file from its data.
+    ///
+    /// PGF files are recognized by their magic number, BWFON files (which have no header) by their size.
+    pub fn detect(data:&[u8]) -> Option<FileType>{
+        if data.len() >= 8 && &data[0x4..0x8] == b"PGF0"{
+            Some(FileType::PGF)
+        } else if bwfon::is_bwfon(data){
+            Some(FileType::BWFON)
+        } else {
+            None
+        }
+    }
+}
 #[derive(Copy, Clone, PartialEq, Eq)]
 pub(crate) struct UCS2(pub u16);
 
@@ -25,7 +41,7 @@ bitflags!{
             const WIDTH_FIX             = 0x800;
             const ACTIVE                = 0x1000;
             const DIRTY                 = 0x1;
-            const CACHE_MED             = 0x0;
+            const CACHE_MED             = 0x0; // no bit, the default cache (as in intraFont)
             const CACHE_LARGE           = 0x4000;
             const CACHE_ASCII           = 0x8000;
             const CACHE_ALL             = 0xC000;
@@ -55,6 +71,7 @@ bitflags!{
             const SCROLL_MASK           = 0x2600;
             const CACHE_MASK            = 0xC000;
             const STRING_MASK           = 0xFF0000;
+            const LAZY_METRICS          = 0x1000000; // decode glyph metrics when chars are first used, not at load time
             const NONE                  = 0x0;
         }
     }
@@ -0,0 +1,152 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::cell::Ref;
+use core::ops::Deref;
+use crate::fontlib::fontlib::{Font, FontFace, PGFHeader};
+use crate::fontlib::bwfon;
+
+/// What a font file says about itself, returned by `Font::info`.
+///
+/// Sizes are in pixels, at size 1. BWFON files have no header: their names are empty and their
+/// revision, version, point size and resolution are 0.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FontInfo{
+    /// Name of the font family ("FTT-NewRodin Pro DB" for the firmware fonts).
+    pub family: String,
+    /// Name of the style in the family ("Regular", "Bold"...).
+    pub style: String,
+    /// Revision of the PGF format (2 or 3).
+    pub revision: u32,
+    pub version: u32,
+    /// Size the glyphs were made for, in points (horizontal, vertical).
+    pub point_size: (f32, f32),
+    /// Resolution the glyphs were made for, in dots per inch (horizontal, vertical).
+    pub resolution: (f32, f32),
+    /// Largest advance of a glyph (horizontal, vertical).
+    pub max_advance: (f32, f32),
+    /// Distance between the baselines of two lines.
+    pub line_height: f32,
+    /// Number of glyphs in the font data (chars can share glyphs).
+    pub glyph_count: usize,
+    /// Number of shadow glyphs.
+    pub shadow_count: usize,
+}
+
+/// A name of the header, up to the first NUL.
+fn header_string(chars: &[char]) -> String{
+    chars.iter().take_while(|c| **c != '\0').collect::<String>().trim_end().into()
+}
+
+impl FontInfo{
+    pub(crate) fn from_header(header: &PGFHeader) -> Self{
+        let from_64ths = |(x, y): (u32, u32)| (x as f32 / 64.0, y as f32 / 64.0);
+        FontInfo{
+            family: header_string(&header.family),
+            style: header_string(&header.style),
+            revision: header.revision,
+            version: header.version,
+            point_size: from_64ths(header.size),
+            resolution: from_64ths(header.resolution),
+            max_advance: from_64ths(header.advance),
+            line_height: header.advance.1 as f32 / 64.0,
+            glyph_count: header.charptr_len as usize,
+            shadow_count: header.shadowmap_len as usize,
+        }
+    }
+
+    pub(crate) fn bwfon(glyph_count: usize) -> Self{
+        FontInfo{
+            family: String::new(),
+            style: String::new(),
+            revision: 0,
+            version: 0,
+            point_size: (0.0, 0.0),
+            resolution: (0.0, 0.0),
+            max_advance: (bwfon::GLYPH_WIDTH as f32, bwfon::GLYPH_HEIGHT as f32),
+            line_height: bwfon::GLYPH_HEIGHT as f32,
+            glyph_count,
+            shadow_count: 0, // BWFON files have no shadows
+        }
+    }
+}
+
+/// Every char a font can draw, in order (see `Font::code_points`).
+///
+/// `F` is the face, or a `Ref` to a face that is shared.
+pub struct CodePoints<F>{
+    face: F,
+    next: u32,
+}
+
+impl<'a, F: Deref<Target = FontFace<'a>>> Iterator for CodePoints<F>{
+    type Item = char;
+
+    fn next(&mut self) -> Option<char>{
+        while self.next <= 0xFFFF{
+            let code = self.next;
+            self.next += 1;
+            match core::char::from_u32(code){
+                Some(c) if self.face.has_char(c) => return Some(c),
+                _ => {} // not in the font, or a surrogate
+            }
+        }
+        None
+    }
+}
+
+impl<'a> FontFace<'a>{
+    /// Names, sizes and glyph counts from the header of the font file.
+    pub fn info(&self) -> &FontInfo{
+        &self.info
+    }
+
+    /// Whether the face has a glyph for `c` (chars outside of the BMP never are in a font).
+    pub fn has_char(&self, c: char) -> bool{
+        c as u32 <= 0xFFFF && self.get_char_id(c as u16) < self.n_chars
+    }
+
+    /// The chars of `text` that neither the face nor its fallback fonts can draw, each one once, in the order
+    /// they first appear.
+    ///
+    /// Newlines are not reported, `print` does not draw them.
+    pub fn missing_chars(&self, text: &str) -> Vec<char>{
+        let drawn = |c: char| self.has_char(c) || (c as u32 <= 0xFFFF && self.fallback_for(c as u16).is_some());
+        let mut missing = Vec::new();
+        for c in text.chars().filter(|c| *c != '\n' && !drawn(*c)){
+            if !missing.contains(&c){
+                missing.push(c);
+            }
+        }
+        missing
+    }
+
+    /// Every char the face can draw, in order.
+    pub fn code_points(&self) -> CodePoints<&Self>{
+        CodePoints{ face: self, next: 0 }
+    }
+}
+
+impl<'a> Font<'a>{
+    /// Names, sizes and glyph counts from the header of the font file.
+    pub fn info(&self) -> FontInfo{
+        self.face().info.clone()
+    }
+
+    /// Whether the font has a glyph for `c` (see `FontFace::has_char`).
+    pub fn has_char(&self, c: char) -> bool{
+        self.face().has_char(c)
+    }
+
+    /// The chars of `text` that the font and its fallback fonts can not draw, each one once, in the order
+    /// they first appear.
+    ///
+    /// Checking that every translated string gives an empty list makes sure the font covers them.
+    pub fn missing_chars(&self, text: &str) -> Vec<char>{
+        self.face().missing_chars(text)
+    }
+
+    /// Every char the font can draw, in order. The face is borrowed until the iterator is dropped.
+    pub fn code_points(&self) -> CodePoints<Ref<'_, FontFace<'a>>>{
+        CodePoints{ face: self.face(), next: 0 }
+    }
+}
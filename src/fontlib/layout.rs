@@ -0,0 +1,576 @@
+use alloc::vec::Vec;
+use core::ops::Range;
+use crate::fontlib::fontlib::{Font, FontFace};
+use crate::fontlib::bwfon;
+use crate::fontlib::glyph::Glyph;
+use crate::fontlib::helper::{PGFFlags, FileType};
+use crate::fontlib::style::{FontColor, FontStyle};
+
+/// Where and how `Font::layout` lays out text.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct LayoutOptions{
+    /// Left side of the text (or its right side / center with `ALIGN_RIGHT` / `ALIGN_CENTER`).
+    pub x: f32,
+    /// Baseline of the first line.
+    pub y: f32,
+    /// With `column > 0`, lines are broken at spaces (or in the middle of words) so they fit in `column` pixels,
+    /// unless the text scrolls (`SCROLL_*` options), in which case it is a single line clipped to the column.
+    pub column: f32,
+    /// Size, colors and options of the text (the angle is not applied to the layout).
+    pub style: FontStyle,
+}
+
+/// A glyph (or shadow glyph) of a `TextLayout`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct PositionedGlyph{
+    /// Index of the glyph in its font (for overlay chars, the sub glyph that is drawn).
+    /// For shadows, the glyph of the char the shadow belongs to.
+    pub glyph_id: u16,
+    /// Index of the shadow glyph if this is a shadow, `None` for the glyph itself.
+    pub shadow_id: Option<u16>,
+    /// 0 for glyphs of the font itself, n for glyphs of the n-th font of its fallback chain (see `Font::set_fallback`).
+    pub fallback: u8,
+    /// Screen coordinates of the bitmap: (left, up, right, down), before rotation.
+    pub rect: (f32, f32, f32, f32),
+    /// Texture coordinates of the bitmap in the texture of its font (in texels): (left, up, right, down).
+    ///
+    /// They are valid until other glyphs are cached in the texture (by laying out or printing other text).
+    pub uv: (f32, f32, f32, f32),
+    /// Texture page of its font the bitmap is in (see `AtlasConfig`).
+    pub page: u8,
+    pub color: FontColor,
+    /// Index in the text of the char this glyph was laid out for (in bytes for `Font::layout`).
+    pub source_index: usize,
+}
+
+impl PositionedGlyph{
+    pub fn is_shadow(&self) -> bool{
+        self.shadow_id.is_some()
+    }
+}
+
+/// A line of a `TextLayout`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LineBox{
+    /// x coordinate of the start of the line.
+    pub left: f32,
+    /// x coordinate of the end of the line (after the advance of its last char).
+    pub right: f32,
+    pub baseline: f32,
+    /// Distance between the baselines of two lines.
+    pub height: f32,
+    /// Chars of the text that are on this line (in bytes for `Font::layout`), including the newline or space it was broken at.
+    pub source: Range<usize>,
+    /// Glyphs (and shadows) of the line in `TextLayout::glyphs`.
+    pub glyphs: Range<usize>,
+}
+
+/// Text laid out by `Font::layout`: everything `print` would draw, without drawing it.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct TextLayout{
+    /// Glyphs and shadows, in the order they were laid out (the shadow of a char comes after its glyphs).
+    pub glyphs: Vec<PositionedGlyph>,
+    pub lines: Vec<LineBox>,
+    /// x coordinate of the end of the text (the value returned by `print`).
+    pub end: f32,
+    /// Horizontal window (left, right) the text is clipped to when it scrolls.
+    pub clip: Option<(f32, f32)>,
+}
+
+impl TextLayout{
+    /// The glyph (not shadow) under (x, y), if any. Rotation is not taken into account.
+    pub fn glyph_at(&self, x: f32, y: f32) -> Option<&PositionedGlyph>{
+        self.glyphs.iter().find(|glyph| {
+            let (left, up, right, down) = glyph.rect;
+            !glyph.is_shadow() && x >= left && x < right && y >= up && y < down
+        })
+    }
+
+    /// The line under y, if any (a line goes from `height` above its baseline down to its baseline).
+    pub fn line_at(&self, y: f32) -> Option<&LineBox>{
+        self.lines.iter().find(|line| y >= line.baseline - line.height && y < line.baseline)
+    }
+}
+
+/// Size of a text, measured by `Font::measure`.
+///
+/// Coordinates are relative to the start of the text, on the baseline of its first line.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct TextMetrics{
+    /// Width of the widest line.
+    pub width: f32,
+    /// From the ascent of the first line to the descent of the last one.
+    pub height: f32,
+    pub line_count: usize,
+    /// Width of every line (from its start to the advance of its last char).
+    pub line_widths: Vec<f32>,
+    /// Distance between the baselines of two lines.
+    pub line_height: f32,
+    /// Highest a glyph of the font goes above the baseline.
+    pub ascent: f32,
+    /// Lowest a glyph of the font goes below the baseline.
+    pub descent: f32,
+    /// Box around every pixel that would be drawn (glyphs and shadows): (left, up, right, down).
+    /// `None` if nothing would be drawn.
+    pub ink: Option<(f32, f32, f32, f32)>,
+}
+
+/// What `FontFace::walk_ucs2` finds in the text.
+pub(crate) enum Placed<'f, 'a>{
+    Glyph(PlacedGlyph<'f, 'a>),
+    /// A finished line (its `glyphs` are left empty).
+    Line(LineBox),
+}
+
+/// A glyph placed on the screen by `FontFace::walk_ucs2` (before rotation).
+pub(crate) struct PlacedGlyph<'f, 'a>{
+    /// Font the glyph comes from (a fallback font for chars that are missing from the main font).
+    pub(crate) font: &'f FontFace<'a>,
+    /// Metrics of the glyph. For BWFON fonts, `offset` already points to the bitmap of the char.
+    pub(crate) glyph: Glyph,
+    /// `CHAR_GLYPH` or `SHADOWGLYPH`.
+    pub(crate) glyph_type: PGFFlags,
+    pub(crate) glyph_id: u16,
+    pub(crate) shadow_id: Option<u16>,
+    pub(crate) fallback: u8,
+    /// Screen coordinates of the bitmap: (left, up, right, down).
+    pub(crate) rect: (f32, f32, f32, f32),
+    pub(crate) uv: (f32, f32, f32, f32),
+    pub(crate) color: FontColor,
+    pub(crate) source_index: usize,
+}
+
+impl<'f, 'a> PlacedGlyph<'f, 'a>{
+    /// Whether the glyph is in the texture at `uv` (it is not when the texture is full of the glyphs of the
+    /// frame, or when its bitmap is corrupt: it must not be drawn then).
+    pub(crate) fn is_cached(&self) -> bool{
+        self.glyph.flags.contains(PGFFlags::CACHED)
+    }
+
+    pub(crate) fn positioned(&self) -> PositionedGlyph{
+        PositionedGlyph{
+            glyph_id: self.glyph_id,
+            shadow_id: self.shadow_id,
+            fallback: self.fallback,
+            rect: self.rect,
+            uv: self.uv,
+            page: self.glyph.page,
+            color: self.color,
+            source_index: self.source_index,
+        }
+    }
+}
+
+/// Texture coordinates of a glyph cached at (x, y), with a quarter texel of margin.
+fn texel_rect(x: u16, y: u16, width: u8, height: u8) -> (f32, f32, f32, f32){
+    (x as f32 - 0.25, y as f32 - 0.25, x as f32 + width as f32 + 0.25, y as f32 + height as f32 + 0.25)
+}
+
+impl<'a> Font<'a>{
+
+    /// Lays out `text` like `print_column_ex` would draw it, with `options.style` instead of the style of the font.
+    ///
+    /// Glyphs that are not in the texture yet are cached, so the texture coordinates of every glyph are valid.
+    /// Source indices are byte offsets in `text`.
+    pub fn layout(&mut self, text: &str, options: &LayoutOptions) -> TextLayout{
+        self.face_mut().layout(text, options)
+    }
+
+    /// Measures `text` drawn with the style of the font, with lines broken to fit in `max_width` pixels
+    /// (the same way as `print_column_ex` breaks them).
+    pub fn measure(&self, text: &str, max_width: Option<f32>) -> TextMetrics{
+        self.face().measure(text, max_width, &self.style())
+    }
+}
+
+impl<'a> FontFace<'a>{
+
+    /// Width of every char with `WIDTH_FIX` (the font's advance is used if `options` do not give one, like in `set_style`).
+    fn fixed_width(&self, options: PGFFlags) -> f32{
+        match (options & PGFFlags::WIDTH_MASK).bits(){
+            0 => ((self.advance.0 as u32 / 8) & PGFFlags::WIDTH_MASK.bits()) as f32,
+            width => width as f32,
+        }
+    }
+
+    /// Width of `text` up to the first newline, drawn with `style`.
+    pub(crate) fn measure_ucs2(&self, text: &[u16], style: &FontStyle) -> f32{
+        let mut x = 0.0f32;
+        let mut i = 0;
+        while i < text.len(){
+            let ucs2 = text[i];
+            if ucs2 == '\n' as u16{
+                break;
+            }
+            let char_id = self.get_char_id(ucs2);
+            if char_id < self.n_chars{
+                let glyph_ptr = if self.filetype == FileType::PGF { char_id as usize } else { 0 };
+                x += if style.options.contains(PGFFlags::WIDTH_FIX) {
+                    self.fixed_width(style.options) * style.size
+                } else {
+                    self.glyph(glyph_ptr).advance as f32 * style.size * 0.25
+                };
+            } else if let Some((n, end)) = self.fallback_run(text, i, text.len()){
+                x += self.fallbacks.chain[n].borrow().measure_ucs2(&text[i..end], &alt_style(style));
+                i = end;
+                continue;
+            }
+            i += 1;
+        }
+        x
+    }
+
+    /// Horizontal window (left, right) text is clipped to when it scrolls in a column that is too small for it.
+    ///
+    /// Only the first frame of the scrolling animation is drawn (the left side of the text).
+    pub(crate) fn scroll_window(&self, x: f32, column: f32, text: &[u16], style: &FontStyle) -> Option<(f32, f32)>{
+        if column <= 0.0 || !style.options.contains(PGFFlags::SCROLL_LEFT){
+            return None
+        }
+        let line: Vec<u16> = text.iter().map(|c| if *c == '\n' as u16 { ' ' as u16 } else { *c }).collect();
+        if self.measure_ucs2(&line, style) <= column{
+            return None
+        }
+        if (style.options & PGFFlags::SCROLL_MASK) == PGFFlags::SCROLL_SEESAW{
+            Some((x - column / 2.0, x + column / 2.0))
+        } else {
+            Some((x, x + column))
+        }
+    }
+
+    /// Lays out `text` like `print_column_ex` would draw it with `options.style` (see `Font::layout`).
+    pub fn layout(&mut self, text: &str, options: &LayoutOptions) -> TextLayout{
+        let mut units = Vec::with_capacity(text.len());
+        let mut offsets = Vec::with_capacity(text.len() + 1);
+        let mut encoder = self.encode(text.as_bytes());
+        loop {
+            let offset = encoder.offset();
+            match encoder.next(){
+                Some(unit) => {
+                    units.push(unit);
+                    offsets.push(offset);
+                },
+                None => break,
+            }
+        }
+        offsets.push(text.len());
+
+        let mut layout = self.layout_ucs2(&units, options);
+        for glyph in layout.glyphs.iter_mut(){
+            glyph.source_index = offsets[glyph.source_index];
+        }
+        for line in layout.lines.iter_mut(){
+            line.source = offsets[line.source.start]..offsets[line.source.end];
+        }
+        layout
+    }
+
+    /// Measures `text` drawn with `style`, with lines broken to fit in `max_width` pixels (see `Font::measure`).
+    pub fn measure(&self, text: &str, max_width: Option<f32>, style: &FontStyle) -> TextMetrics{
+        let text: Vec<u16> = self.encode(text.as_bytes()).collect();
+        let style = *style;
+        let options = LayoutOptions{ x: 0.0, y: 0.0, column: max_width.unwrap_or(0.0), style };
+        let (ascent, descent) = self.extents;
+        let mut metrics = TextMetrics{
+            line_height: self.advance.1 as f32 * style.size * 0.25,
+            ascent: ascent * style.size,
+            descent: descent * style.size,
+            ..TextMetrics::default()
+        };
+        let mut last_baseline = 0.0f32;
+        self.walk_ucs2(&text, &options, &mut |placed| match placed{
+            Placed::Glyph(glyph) => {
+                let (left, up, right, down) = glyph.rect;
+                metrics.ink = Some(match metrics.ink{
+                    Some((l, u, r, d)) => (l.min(left), u.min(up), r.max(right), d.max(down)),
+                    None => (left, up, right, down),
+                });
+            },
+            Placed::Line(line) => {
+                metrics.line_widths.push(line.right - line.left);
+                last_baseline = line.baseline;
+            },
+        });
+        metrics.line_count = metrics.line_widths.len();
+        metrics.width = metrics.line_widths.iter().fold(0.0, |width, line| line.max(width));
+        if metrics.line_count > 0{
+            metrics.height = metrics.ascent + last_baseline + metrics.descent;
+        }
+        metrics
+    }
+
+    /// Lays out UCS2 `text` (source indices are indices in `text`).
+    pub(crate) fn layout_ucs2(&mut self, text: &[u16], options: &LayoutOptions) -> TextLayout{
+        let mut layout = TextLayout::default();
+        self.layout_ucs2_into(text, options, &mut layout);
+        layout
+    }
+
+    /// Lays out UCS2 `text` in `layout`, reusing its buffers.
+    pub(crate) fn layout_ucs2_into(&mut self, text: &[u16], options: &LayoutOptions, layout: &mut TextLayout){
+        self.begin_text();
+        self.cache_ucs2(text);
+        layout.glyphs.clear();
+        layout.lines.clear();
+        layout.clip = self.scroll_window(options.x, options.column, text, &options.style);
+        let mut line_start = 0;
+        let glyphs = &mut layout.glyphs;
+        let lines = &mut layout.lines;
+        layout.end = self.walk_ucs2(text, options, &mut |placed| match placed{
+            Placed::Glyph(glyph) if glyph.is_cached() => glyphs.push(glyph.positioned()),
+            Placed::Glyph(_) => (), // could not be cached, its texture coordinates belong to another glyph
+            Placed::Line(mut line) => {
+                line.glyphs = line_start..glyphs.len();
+                line_start = glyphs.len();
+                lines.push(line);
+            },
+        });
+    }
+
+    /// Caches the glyphs (and shadows) needed to draw `text`, in this font or in its fallback fonts.
+    ///
+    /// Glyphs used since `begin_text` are never evicted to make room for the others.
+    pub(crate) fn cache_ucs2(&mut self, text: &[u16]){
+        let mut changed = false;
+        let mut i = 0;
+        while i < text.len(){
+            let char_id = self.get_char_id(text[i]) as usize;
+            if char_id >= self.n_chars as usize{
+                i = match self.fallback_run(text, i, text.len()){
+                    Some((n, end)) => {
+                        self.fallbacks.chain[n].borrow_mut().cache_ucs2(&text[i..end]);
+                        end
+                    },
+                    None => i + 1,
+                };
+                continue;
+            }
+            i += 1;
+            if self.filetype == FileType::PGF{
+                let glyph = self.glyph(char_id);
+                if glyph.flags.contains(PGFFlags::BMP_OVERLAY){
+                    for n in 0..3{
+                        let glyph_id = self.get_char_id(self.overlay_char(char_id, n)) as usize;
+                        if glyph_id < self.n_chars as usize{
+                            let cached = self.glyph(glyph_id).flags.contains(PGFFlags::CACHED);
+                            changed |= self.use_glyph(glyph_id, PGFFlags::CHAR_GLYPH, cached);
+                        }
+                    }
+                } else {
+                    changed |= self.use_glyph(char_id, PGFFlags::CHAR_GLYPH, glyph.flags.contains(PGFFlags::CACHED));
+                }
+                let shadow_id = glyph.shadow_id as usize;
+                if let Some(shadow) = self.shadow_glyph(shadow_id){
+                    changed |= self.use_glyph(shadow_id, PGFFlags::SHADOWGLYPH, shadow.flags.contains(PGFFlags::CACHED));
+                }
+            } else {
+                let cached = self.glyphs_bw[char_id].flags.contains(PGFFlags::CACHED);
+                changed |= self.use_glyph(char_id, PGFFlags::CHAR_GLYPH, cached); // BWFON glyphs have no shadow
+            }
+        }
+        if changed{
+            self.options.insert(PGFFlags::DIRTY);
+        }
+    }
+
+    /// Places every glyph (and shadow glyph) of `text` like `print_column_ex` does, and gives them to `emit`
+    /// (with every line once it is finished).
+    ///
+    /// Returns the x coordinate of the end of the text.
+    pub(crate) fn walk_ucs2(&self, text: &[u16], options: &LayoutOptions, emit: &mut dyn for<'f> FnMut(Placed<'f, 'a>)) -> f32{
+        let (x, y, column, style) = (options.x, options.y, options.column, &options.style);
+        let scroll = self.scroll_window(x, column, text, style);
+        let single_line: Vec<u16>;
+        let text = if column > 0.0 && style.options.contains(PGFFlags::SCROLL_LEFT){
+            single_line = text.iter().map(|c| if *c == '\n' as u16 { ' ' as u16 } else { *c }).collect();
+            &single_line[..]
+        } else {
+            text
+        };
+        let scale = style.size;
+        let line_height = self.advance.1 as f32 * scale * 0.25;
+        let align = style.options & PGFFlags::ALIGN_MASK;
+        let (mut left, mut width, mut baseline) = (x, 0.0f32, y - line_height);
+        let (mut eol, mut fill, mut line_start) = (0usize, 0.0f32, 0usize);
+
+        let mut i = 0;
+        while i < text.len(){
+            let ucs2 = text[i];
+            if i == 0 || ucs2 == '\n' as u16 || (column > 0.0 && scroll.is_none() && i >= eol && ucs2 != ' ' as u16){
+                if i > 0{
+                    emit(Placed::Line(LineBox{ left, right: left + width, baseline, height: line_height, source: line_start..i, glyphs: 0..0 }));
+                    line_start = i;
+                }
+                // new line: find where it ends and where it starts
+                if column > 0.0 && style.options.contains(PGFFlags::SCROLL_LEFT){
+                    eol = text.len();
+                    left = match scroll{
+                        Some((window_left, _)) => window_left,
+                        None => x,
+                    };
+                } else if column > 0.0{
+                    // automatic line-break
+                    let mut n_spaces = -1i32;
+                    let mut end = None;
+                    fill = 0.0;
+                    let mut j = i;
+                    while j < text.len(){
+                        if text[j] == '\n' as u16{
+                            end = Some(j); // newline reached -> no auto-line break
+                            break;
+                        }
+                        if text[j] == ' ' as u16{
+                            n_spaces += 1; // space found for padding or eol
+                            end = Some(j);
+                        }
+                        if self.measure_ucs2(&text[i..=j], style) > column{
+                            // line too long -> line break
+                            let line_end = end.unwrap_or(j); // line break in the middle of the word
+                            end = Some(line_end);
+                            if n_spaces > 0{
+                                fill = (column - self.measure_ucs2(&text[i..line_end], style)) / n_spaces as f32;
+                            }
+                            break;
+                        }
+                        j += 1;
+                    }
+                    eol = match end{
+                        Some(end) if j < text.len() => end,
+                        _ => {
+                            let mut end = text.len(); // last line
+                            while end > 1 && text[end - 1] == ' ' as u16{
+                                end -= 1;
+                            }
+                            end
+                        }
+                    };
+                    left = x;
+                    let line_width = self.measure_ucs2(&text[i..eol.max(i)], style);
+                    if align == PGFFlags::ALIGN_RIGHT{
+                        left -= line_width;
+                    } else if align == PGFFlags::ALIGN_CENTER{
+                        left -= line_width / 2.0;
+                    }
+                } else {
+                    // no column boundary -> display everything
+                    left = x;
+                    let line = if ucs2 == '\n' as u16 { &text[i + 1..] } else { &text[i..] };
+                    if align == PGFFlags::ALIGN_RIGHT{
+                        left -= self.measure_ucs2(line, style);
+                    } else if align == PGFFlags::ALIGN_CENTER{
+                        left -= self.measure_ucs2(line, style) / 2.0;
+                    }
+                }
+                width = 0.0;
+                baseline += line_height;
+            }
+
+            let char_id = self.get_char_id(ucs2);
+            if char_id < self.n_chars{
+                let glyph_ptr = if self.filetype == FileType::PGF { char_id as usize } else { 0 };
+                let char_glyph = self.glyph(glyph_ptr);
+                let advance = char_glyph.advance as f32;
+                if style.options.contains(PGFFlags::WIDTH_FIX){
+                    width += (self.fixed_width(style.options) / 2.0 - advance / 8.0) * scale; // center glyphs for monospace
+                }
+
+                // sub glyphs (overlay glyphs are made of up to 3 other glyphs)
+                let mut drawn = false;
+                let overlay = self.filetype == FileType::PGF && (char_glyph.flags & PGFFlags::BMP_OVERLAY) == PGFFlags::BMP_OVERLAY;
+                for n in 0..if overlay { 3 } else { 1 }{
+                    let glyph_id = if overlay { self.get_char_id(self.overlay_char(char_id as usize, n)) } else { char_id };
+                    if glyph_id >= self.n_chars{
+                        continue;
+                    }
+                    let mut glyph = if self.filetype == FileType::PGF { self.glyph(glyph_id as usize) } else { self.glyph(0) };
+                    if self.filetype == FileType::BWFON{
+                        glyph.offset = (glyph_id as usize * bwfon::GLYPH_STRIDE) as u32;
+                    }
+                    let (tex_x, tex_y) = if self.filetype == FileType::PGF { (glyph.x, glyph.y) } else {
+                        let cached = &self.glyphs_bw[glyph_id as usize];
+                        glyph.flags = cached.flags | PGFFlags::BMP_HORIZONTAL_ROWS;
+                        glyph.page = cached.page;
+                        (cached.x, cached.y)
+                    };
+                    let xl = left + width + glyph.left as f32 * scale;
+                    let yu = baseline - glyph.top as f32 * scale;
+                    emit(Placed::Glyph(PlacedGlyph{
+                        font: self,
+                        glyph,
+                        glyph_type: PGFFlags::CHAR_GLYPH,
+                        glyph_id,
+                        shadow_id: None,
+                        fallback: 0,
+                        rect: (xl, yu, xl + glyph.width as f32 * scale, yu + glyph.height as f32 * scale),
+                        uv: texel_rect(tex_x, tex_y, glyph.width, glyph.height),
+                        color: style.color,
+                        source_index: i,
+                    }));
+                    drawn = true;
+                }
+
+                // shadow (only if something was drawn)
+                let shadow_ptr = if self.filetype == FileType::PGF { char_glyph.shadow_id as usize } else { 0 };
+                if let (true, Some(shadow)) = (drawn && self.n_shadows > 0, self.shadow_glyph(shadow_ptr)){
+                    let shadow_scale = scale * 64.0 / self.shadow_scale as f32;
+                    let xl = left + width + shadow.left as f32 * shadow_scale;
+                    let yu = baseline - shadow.top as f32 * shadow_scale;
+                    emit(Placed::Glyph(PlacedGlyph{
+                        font: self,
+                        glyph: shadow,
+                        glyph_type: PGFFlags::SHADOWGLYPH,
+                        glyph_id: char_id,
+                        shadow_id: Some(shadow_ptr as u16),
+                        fallback: 0,
+                        rect: (xl, yu, xl + shadow.width as f32 * shadow_scale, yu + shadow.height as f32 * shadow_scale),
+                        uv: texel_rect(shadow.x, shadow.y, shadow.width, shadow.height),
+                        color: style.shadow_color,
+                        source_index: i,
+                    }));
+                }
+
+                // advance
+                if style.options.contains(PGFFlags::WIDTH_FIX){
+                    width += (self.fixed_width(style.options) / 2.0 + advance / 8.0) * scale;
+                } else {
+                    width += advance * scale * 0.25;
+                }
+                if ucs2 == ' ' as u16 && align == PGFFlags::ALIGN_FULL{
+                    width += fill;
+                }
+            } else if let Some((n, end)) = self.fallback_run(text, i, if column > 0.0 && scroll.is_none() { eol.max(i + 1) } else { text.len() }){
+                // chars that are not in this font: the run a fallback font has is laid out in one go
+                let fallback = self.fallbacks.chain[n].borrow();
+                let alt_options = LayoutOptions{ x: left + width, y: baseline, column: 0.0, style: alt_style(style) };
+                let start = i;
+                width += fallback.walk_ucs2(&text[start..end], &alt_options, &mut |placed| {
+                    if let Placed::Glyph(glyph) = placed{
+                        emit(Placed::Glyph(PlacedGlyph{ fallback: n as u8 + 1, source_index: start + glyph.source_index, ..glyph }));
+                    }
+                }) - (left + width);
+                i = end;
+                continue;
+            }
+            i += 1;
+        }
+        if !text.is_empty(){
+            emit(Placed::Line(LineBox{ left, right: left + width, baseline, height: line_height, source: line_start..text.len(), glyphs: 0..0 }));
+        }
+
+        match scroll{
+            Some(_) => x,
+            None => left + width,
+        }
+    }
+}
+
+/// Style used for the fallback fonts: only the width and cache options are kept (like in intraFont).
+fn alt_style(style: &FontStyle) -> FontStyle{
+    FontStyle{
+        size: style.size,
+        color: style.color,
+        shadow_color: style.shadow_color,
+        angle: style.angle,
+        options: style.options & (PGFFlags::WIDTH_MASK | PGFFlags::CACHE_MASK),
+    }
+}
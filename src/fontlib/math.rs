@@ -0,0 +1,67 @@
+use core::f32::consts::PI;
+
+/// Cosine of an angle in radians.
+///
+/// Uses the VFPU on the PSP, and a polynomial approximation everywhere else.
+pub fn cosf(rad: f32) -> f32{
+    #[cfg(feature = "psp")]
+    {
+        unsafe { cosf32(rad) }
+    }
+    #[cfg(not(feature = "psp"))]
+    {
+        soft_cosf(rad)
+    }
+}
+
+/// Cosine computed without the VFPU (Taylor series, precise to ~1e-6 after range reduction).
+pub fn soft_cosf(rad: f32) -> f32{
+    let tau = 2.0 * PI;
+    let mut x = rad - ((rad / tau) as i32) as f32 * tau; // somewhere in (-2PI, 2PI)
+    if x > PI{
+        x -= tau;
+    } else if x < -PI{
+        x += tau;
+    }
+    if x < 0.0{
+        x = -x; // cos is even
+    }
+    let (x, sign) = if x > PI / 2.0 { (PI - x, -1.0) } else { (x, 1.0) }; // cos(x) = -cos(PI - x)
+    let x2 = x * x;
+    sign * (1.0 - x2 / 2.0 * (1.0 - x2 / 12.0 * (1.0 - x2 / 30.0 * (1.0 - x2 / 56.0 * (1.0 - x2 / 90.0)))))
+}
+
+// TODO: cosf vs cosf32? which makes intrinsics::cosf32 work?
+#[cfg(feature = "psp")]
+#[allow(non_snake_case)]
+pub unsafe fn cosf32(rad: f32) -> f32 {
+    let out;
+
+    vfpu_asm!(
+    .mips "mfc1 $$t0, $1";
+
+    mfv t1, S000;
+    mfv t2, S001;
+
+    mtv t0, S000;
+    vcst_s S001, VFPU_2_PI;
+    vmul_s S000, S000, S001;
+    vcos_s S000, S000;
+    mfv t0, S000;
+
+    mtv t1, S000;
+    mtv t2, S001;
+
+    .mips "mtc1 $$t0, $0";
+
+    : "=f"(out) : "f"(rad) : "$8", "$9", "$10", "memory" : "volatile"
+);
+
+    out
+}
+
+/// Largest integer less than or equal to `x` (`core` has no `f32::floor` without `std`).
+pub fn floorf(x: f32) -> f32{
+    let truncated = (x as i32) as f32;
+    if truncated > x { truncated - 1.0 } else { truncated }
+}
@@ -0,0 +1,251 @@
+use crate::fontlib::fontlib::{Font, FontFace};
+use crate::fontlib::backend::{RenderBackend, Primitive, TextureImage};
+use crate::fontlib::vertex::FontVertex;
+use crate::fontlib::style::{FontColor, FontStyle};
+use crate::fontlib::layout::{LayoutOptions, PositionedGlyph, TextLayout};
+use crate::fontlib::rotation::Rotation;
+use crate::fontlib::error::FontError;
+use crate::fontlib::ccclib::Codepages;
+use crate::fontlib::debug::io_write;
+use crate::fontlib::helper::PGFFlags;
+use alloc::vec::Vec;
+
+/// Color lookup table of the font textures: white, with the 4 bit value of the pixel as alpha.
+pub(crate) fn font_clut() -> [u32; 16]{
+    let mut clut = [0u32; 16];
+    for n in 0..16u32{
+        clut[n as usize] = ((n * 17) << 24) | 0xFFFFFF;
+    }
+    clut
+}
+
+impl<'a> Font<'a>{
+
+    /// Prints `text` with `backend`, with (x, y) on the baseline of the first line.
+    ///
+    /// Returns the x coordinate of the end of the text, or an error if `backend` could not draw it.
+    pub fn print_to<B: RenderBackend + ?Sized>(&mut self, backend: &mut B, x: f32, y: f32, text: &str) -> Result<f32, FontError>{
+        self.print_column_to(backend, x, y, 0.0, text, text.len() as i32)
+    }
+
+    /// Prints the first `length` chars of `text` with `backend`, in a column of `column` pixels.
+    ///
+    /// Text that is too long for its column scrolls with the `SCROLL_LEFT` and `SCROLL_SEESAW` options.
+    /// Like in intraFont, the frame of the animation is kept in the low bits of `x`: print again with the
+    /// returned x on the next frame to animate it.
+    pub fn print_column_to<B: RenderBackend + ?Sized>(&mut self, backend: &mut B, x: f32, y: f32, column: f32, text: &str, length: i32) -> Result<f32, FontError>{
+        if text.is_empty() || length <= 0{
+            return Ok(x)
+        }
+        let mut face = self.face_mut();
+        let mut units = core::mem::take(&mut face.text_buffer);
+        units.clear();
+        units.extend(face.encode(text.as_bytes()).take(length as usize));
+        let end = face.print_column_ucs2_to(backend, x, y, column, &units, &self.style(), &self.rotation);
+        face.text_buffer = units; // kept for the next print
+        end
+    }
+
+    /// Prints `chars` with `backend`, with (x, y) on the baseline of the first line.
+    ///
+    /// Chars outside of the BMP give the replacement char of the font (or surrogate pairs, like `encode`).
+    pub fn print_chars_to<B: RenderBackend + ?Sized, I: IntoIterator<Item = char>>(&mut self, backend: &mut B, x: f32, y: f32, chars: I) -> Result<f32, FontError>{
+        let mut face = self.face_mut();
+        let mut units = core::mem::take(&mut face.text_buffer);
+        units.clear();
+        let mut buffer = [0u16; 2];
+        for c in chars{
+            match c.encode_utf16(&mut buffer){
+                [unit] => units.push(*unit),
+                pair if face.utf8_surrogates => units.extend_from_slice(pair),
+                _ => units.push(face.replacement_char),
+            }
+        }
+        let end = face.print_column_ucs2_to(backend, x, y, 0.0, &units, &self.style(), &self.rotation);
+        face.text_buffer = units;
+        end
+    }
+
+    /// Prints UCS2 `text` with `backend`, with (x, y) on the baseline of the first line.
+    pub fn print_ucs2_to<B: RenderBackend + ?Sized>(&mut self, backend: &mut B, x: f32, y: f32, text: &[u16]) -> Result<f32, FontError>{
+        self.face_mut().print_column_ucs2_to(backend, x, y, 0.0, text, &self.style(), &self.rotation)
+    }
+
+    /// Prints `text`, encoded in the codepage of the `STRING_*` option of the font, with `backend`.
+    ///
+    /// Like in intraFont, the text ends at the first NUL byte (if there is one).
+    pub fn print_bytes_to<B: RenderBackend + ?Sized>(&mut self, backend: &mut B, x: f32, y: f32, text: &[u8]) -> Result<f32, FontError>{
+        let text = match text.iter().position(|byte| *byte == 0){
+            Some(end) => &text[..end],
+            None => text,
+        };
+        let mut face = self.face_mut();
+        let text = match Codepages::from_options(self.style.options){
+            Codepages::UTF8 => face.encode(text).collect(),
+            codepage => codepage.decode_ucs2(text),
+        };
+        face.print_column_ucs2_to(backend, x, y, 0.0, &text, &self.style(), &self.rotation)
+    }
+}
+
+impl<'a> FontFace<'a>{
+
+    /// Gives the CLUT and the texture page `page` of the font to `backend`.
+    pub(crate) fn activate_on<B: RenderBackend + ?Sized>(&self, backend: &mut B, page: u8){
+        io_write("PSP-FONT ACTIVATION SEQUENCE...\n");
+        let texture = &self.textures[page as usize];
+        backend.load_clut(&font_clut());
+        backend.upload_texture(&TextureImage{
+            width: texture.width,
+            height: texture.height,
+            swizzled: texture.swizzled,
+            data: &texture.data,
+        });
+        io_write("PSP-FONT ACTIVATION SEQUENCE COMPLETE\n");
+    }
+
+    /// Prints UCS2 `text` with `style`, rotated by `rotation`. The layout and the vertices are built in buffers
+    /// of the face, so printing does not allocate once they are big enough (except for text that scrolls).
+    pub(crate) fn print_column_ucs2_to<B: RenderBackend + ?Sized>(&mut self, backend: &mut B, x: f32, y: f32, column: f32, text: &[u16], style: &FontStyle, rotation: &Rotation) -> Result<f32, FontError>{
+        if text.is_empty(){
+            return Ok(x)
+        }
+        let mut options = LayoutOptions{ x, y, column, style: *style };
+        let mut next_x = None;
+        if column > 0.0 && style.options.contains(PGFFlags::SCROLL_LEFT){
+            let line: Vec<u16> = text.iter().map(|c| if *c == '\n' as u16 { ' ' as u16 } else { *c }).collect();
+            next_x = Some(self.scroll_frame(backend, &mut options, &line));
+        }
+
+        let mut layout = core::mem::take(&mut self.layout_buffer);
+        let mut vertices = core::mem::take(&mut self.font_vertices);
+        self.layout_ucs2_into(text, &options, &mut layout);
+        let drawn = self.draw_layout(backend, &layout, (options.x, options.y), &mut vertices, rotation);
+        let end = layout.end;
+        self.layout_buffer = layout;
+        self.font_vertices = vertices;
+        drawn?;
+
+        if let Some(next_x) = next_x{
+            backend.reset_scissor();
+            return Ok(next_x)
+        }
+        Ok(end)
+    }
+
+    /// Moves (and fades) the text of `options` for the current frame of its scrolling animation, and clips it to its column.
+    ///
+    /// Returns the x to give back on the next frame.
+    fn scroll_frame<B: RenderBackend + ?Sized>(&self, backend: &mut B, options: &mut LayoutOptions, text: &[u16]) -> f32{
+        let (x, column) = (options.x, options.column);
+        let left = (x as i32) as f32;
+        let mut counter = x.to_bits();
+        let count = counter.wrapping_sub(left.to_bits()) as i32;
+        let mut text_width = self.measure_ucs2(text, &options.style) as i32;
+        if text_width as f32 <= column{
+            options.x = left;
+            return x
+        }
+        let fade = |color: FontColor| FontColor::from_bits_truncate((color.bits() & 0x00FFFFFF) | ((((color.bits() >> 24) * (count - text_width - 90) as u32) / 30) << 24));
+        // the layout starts the text at the left of the window (x - column/2 for SCROLL_SEESAW)
+        let mut shift = 0.0f32;
+        match options.style.options & PGFFlags::SCROLL_MASK{
+            PGFFlags::SCROLL_LEFT => {
+                backend.set_scissor((left - 2.0) as i32, 0, (left + column + 4.0) as i32, 274);
+                if count < 60{
+                    // show initial text for 1s
+                } else if count < text_width + 90{
+                    shift = count as f32 - 60.0;
+                } else if count < text_width + 120{
+                    options.style.color = fade(options.style.color);
+                    options.style.shadow_color = fade(options.style.shadow_color);
+                } else {
+                    counter = left.to_bits(); // reset counter
+                }
+            },
+            PGFFlags::SCROLL_SEESAW => {
+                backend.set_scissor((left - column / 2.0 - 2.0) as i32, 0, (left + column + 4.0) as i32, 272);
+                text_width -= column as i32;
+                if count < 60{
+                    // show initial text (left side) for 1s
+                } else if count < text_width + 60{
+                    shift = count as f32 - 60.0; // scroll left
+                } else if count < text_width + 120{
+                    shift = text_width as f32; // show right side for 1s
+                } else if count < 2 * text_width + 120{
+                    shift = 2.0 * text_width as f32 - count as f32 + 120.0; // scroll right
+                } else {
+                    counter = left.to_bits(); // reset counter
+                }
+            },
+            _ => {}
+        }
+        options.x = left - shift;
+        f32::from_bits(counter.wrapping_add(1))
+    }
+
+    /// Draws a layout of this face with `backend` (rotated around `origin` by `rotation`).
+    ///
+    /// Glyphs of the fallback fonts are drawn first, with their own textures, and there is one draw call per
+    /// texture page. When the glyphs of a font are on several pages, all its shadows are drawn before its glyphs.
+    /// `vertices` is used as a buffer.
+    pub(crate) fn draw_layout<B: RenderBackend + ?Sized>(&self, backend: &mut B, layout: &TextLayout, origin: (f32, f32), vertices: &mut Vec<FontVertex>, rotation: &Rotation) -> Result<(), FontError>{
+        let fallbacks = layout.glyphs.iter().map(|glyph| glyph.fallback).max().unwrap_or(0);
+        let primitive = if rotation.is_rotated { Primitive::Triangles } else { Primitive::Sprites };
+        for fallback in (0..=fallbacks).rev(){
+            let face;
+            let font = match fallback{
+                0 => self,
+                n => match self.fallbacks.chain.get(n as usize - 1){
+                    Some(fallback_face) => {
+                        face = fallback_face.borrow();
+                        &*face
+                    },
+                    None => continue,
+                },
+            };
+            let pages = layout.glyphs.iter().filter(|glyph| glyph.fallback == fallback).map(|glyph| glyph.page).max().unwrap_or(0);
+            // shadows first, so they are below the glyphs (of every page)
+            let passes: &[Option<bool>] = if pages > 0 { &[Some(true), Some(false)] } else { &[None] };
+            for pass in passes.iter(){
+                for page in 0..=pages{
+                    vertices.clear();
+                    for shadows in [true, false].iter().filter(|shadows| pass.map_or(true, |pass| pass == **shadows)){
+                        for glyph in layout.glyphs.iter().filter(|glyph| glyph.fallback == fallback && glyph.page == page && glyph.is_shadow() == *shadows){
+                            Self::quad(vertices, rotation, origin, glyph);
+                        }
+                    }
+                    if vertices.is_empty() && (fallback > 0 || pages > 0){
+                        continue;
+                    }
+                    font.activate_on(backend, page); // And then, there was light...
+                    backend.draw(primitive, vertices)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds the vertices of a glyph (rotated around `origin` if needed).
+    fn quad(vertices: &mut Vec<FontVertex>, rotation: &Rotation, origin: (f32, f32), glyph: &PositionedGlyph){
+        let (xl, yu, xr, yd) = glyph.rect;
+        let (ul, vu, ur, vd) = glyph.uv;
+        let c = glyph.color.bits();
+        if rotation.is_rotated{
+            // Up-left, up-right, down-right, down-left
+            let corners = [(ul, vu, xl, yu), (ur, vu, xr, yu), (ur, vd, xr, yd), (ul, vd, xl, yd)];
+            let mut v = [FontVertex::default(); 4];
+            for (vertex, (u, tv, vx, vy)) in v.iter_mut().zip(corners.iter()){
+                // x' = x cos θ - y sin θ
+                // y' = x sin θ + y cos θ
+                let (rx, ry) = rotation.apply(origin, (*vx, *vy));
+                *vertex = FontVertex{ u: *u, v: *tv, c, x: rx, y: ry, z: 0.0 };
+            }
+            vertices.extend_from_slice(&[v[0], v[1], v[2], v[3], v[0], v[2]]);
+        } else {
+            // Up-left, down-right
+            vertices.push(FontVertex{ u: ul, v: vu, c, x: xl, y: yu, z: 0.0 });
+            vertices.push(FontVertex{ u: ur, v: vd, c, x: xr, y: yd, z: 0.0 });
+        }
+    }
+}
@@ -0,0 +1,172 @@
+use alloc::vec::Vec;
+use crate::fontlib::fontlib::{Font, FontFace};
+use crate::fontlib::bit_reader::BitReader;
+use crate::fontlib::error::FontError;
+use crate::fontlib::glyph::Glyph;
+use crate::fontlib::helper::{PGFFlags, FileType};
+
+/// The decoded pixels of a single glyph.
+///
+/// Coverage is stored with 8 bits per pixel (0 = empty, 255 = fully covered), row after row.
+/// The original fonts only have 4 bits per pixel, `to_4bpp` gives them back packed two per byte.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GlyphBitmap{
+    pub width: u32,
+    pub height: u32,
+    /// Distance (in pixels) from the pen position to the left side of the bitmap.
+    pub left: i32,
+    /// Distance (in pixels) from the baseline to the top of the bitmap.
+    pub top: i32,
+    /// How far the pen moves after this glyph (in pixels, 0 for shadows).
+    pub advance: f32,
+    /// Scale the bitmap must be drawn at (shadow glyphs are stored at a different scale).
+    pub scale: f32,
+    pub data: Vec<u8>,
+}
+
+impl GlyphBitmap{
+    /// Coverage of the pixel at (x, y), or 0 if it is outside of the bitmap.
+    pub fn pixel(&self, x: u32, y: u32) -> u8{
+        if x >= self.width || y >= self.height{
+            return 0
+        }
+        self.data[(x + y * self.width) as usize]
+    }
+
+    /// Coverage packed with 4 bits per pixel (low nibble first), like in the font textures.
+    pub fn to_4bpp(&self) -> Vec<u8>{
+        let mut packed = vec![0u8; (self.data.len() + 1) / 2];
+        for (i, coverage) in self.data.iter().enumerate(){
+            packed[i / 2] |= (coverage >> 4) << ((i & 1) * 4);
+        }
+        packed
+    }
+}
+
+/// Decodes the bitmap of `glyph` from `data`, calling `put(x, y, value)` with the 4 bit value of every pixel.
+///
+/// - PGF bitmaps are RLE compressed nibbles, stored either in horizontal or in vertical rows.
+/// - BWFON glyphs are uncompressed, 1 bit/pixel with the leftmost pixel in the most significant bit.
+pub(crate) fn decode_glyph<F>(data: &[u8], filetype: FileType, glyph: &Glyph, mut put: F) -> Result<(), FontError>
+    where F: FnMut(u32, u32, u8)
+{
+    let (width, height) = (glyph.width as u32, glyph.height as u32);
+    let mut reader = BitReader::at(data, glyph.offset as usize * 8, "glyph bitmap")?;
+
+    if filetype == FileType::PGF{
+        let n_pixels = width * height;
+        let mut i = 0;
+        let mut value = 0;
+        while i < n_pixels{
+            let nibble = reader.read_bits(4)?;
+            let run = if nibble < 8{
+                value = reader.read_bits(4)?; // one value repeated nibble + 1 times
+                nibble + 1
+            } else {
+                16 - nibble // 16 - nibble values follow
+            };
+            for _ in 0..run{
+                if i >= n_pixels{ break; }
+                if nibble >= 8{
+                    value = reader.read_bits(4)?;
+                }
+                if glyph.flags.contains(PGFFlags::BMP_HORIZONTAL_ROWS){
+                    put(i % width, i / width, value as u8);
+                } else {
+                    put(i / height, i % height, value as u8);
+                }
+                i += 1;
+            }
+        }
+    } else {
+        for y in 0..height{
+            let row = reader.read_bits(width)?; // a whole row at once
+            for x in 0..width{
+                let value = ((row >> x) & 1) * 0x0F; // scale 1 bit/pix to 4 bit/pix
+                put(7 - (x & 7) + (x & 248), y, value as u8); // most significant bit first in every byte
+            }
+        }
+    }
+    Ok(())
+}
+
+impl<'a> Font<'a>{
+
+    /// Decodes the glyph of a character into an owned bitmap (see `FontFace::rasterize_glyph`).
+    pub fn rasterize_glyph(&self, ch: char) -> Option<GlyphBitmap>{
+        self.face().rasterize_glyph(ch)
+    }
+
+    /// Decodes the shadow glyph of a character into an owned bitmap (see `FontFace::rasterize_shadow`).
+    pub fn rasterize_shadow(&self, ch: char) -> Option<GlyphBitmap>{
+        self.face().rasterize_shadow(ch)
+    }
+}
+
+impl<'a> FontFace<'a>{
+
+    /// Decodes the glyph of a character into an owned bitmap.
+    ///
+    /// Returns `None` if the font has no glyph for `ch`, or if the glyph data is corrupt.
+    /// Overlay glyphs (made of several other glyphs) can not be rasterized on their own.
+    pub fn rasterize_glyph(&self, ch: char) -> Option<GlyphBitmap>{
+        let char_id = self.rasterizable_char_id(ch)?;
+        let mut glyph = self.glyph(if self.filetype == FileType::PGF { char_id } else { 0 });
+        if self.filetype == FileType::BWFON{
+            glyph.offset = (char_id * crate::fontlib::bwfon::GLYPH_STRIDE) as u32;
+        }
+        let mut bitmap = self.rasterize(&glyph)?;
+        bitmap.advance = glyph.advance as f32 * 0.25;
+        Some(bitmap)
+    }
+
+    /// Decodes the shadow glyph of a character into an owned bitmap.
+    ///
+    /// Shadows are stored at a smaller scale than normal glyphs, `GlyphBitmap::scale` tells how much bigger they are drawn.
+    pub fn rasterize_shadow(&self, ch: char) -> Option<GlyphBitmap>{
+        let char_id = self.rasterizable_char_id(ch)?;
+        let shadow_id = if self.filetype == FileType::PGF { self.glyph(char_id).shadow_id as usize } else { 0 };
+        let glyph = self.shadow_glyph(shadow_id)?;
+        let mut bitmap = self.rasterize(&glyph)?;
+        bitmap.scale = 64.0 / self.shadow_scale as f32;
+        Some(bitmap)
+    }
+
+    fn rasterizable_char_id(&self, ch: char) -> Option<usize>{
+        if ch as u32 > 0xFFFF{
+            return None // fonts only hold UCS2 characters
+        }
+        let char_id = self.get_char_id(ch as u16);
+        if char_id >= self.n_chars{
+            return None
+        }
+        Some(char_id as usize)
+    }
+
+    pub(crate) fn rasterize(&self, glyph: &Glyph) -> Option<GlyphBitmap>{
+        let mut bitmap = GlyphBitmap{
+            width: glyph.width as u32,
+            height: glyph.height as u32,
+            left: glyph.left as i32,
+            top: glyph.top as i32,
+            advance: 0.0,
+            scale: 1.0,
+            data: vec![0u8; glyph.width as usize * glyph.height as usize],
+        };
+        if bitmap.data.is_empty(){
+            return Some(bitmap) // nothing to decode (spaces...)
+        }
+        if !glyph.flags.contains(PGFFlags::BMP_HORIZONTAL_ROWS) == !glyph.flags.contains(PGFFlags::BMP_VERTICAL_ROWS){
+            return None // overlay glyph
+        }
+        let data = self.glyph_data();
+        let width = bitmap.width;
+        let pixels = &mut bitmap.data;
+        decode_glyph(data, self.filetype, glyph, |x, y, value| {
+            if let Some(pixel) = pixels.get_mut((x + y * width) as usize){
+                *pixel = value * 17; // 0xF -> 0xFF
+            }
+        }).ok()?;
+        Some(bitmap)
+    }
+}
@@ -1,7 +1,36 @@
+use core::f32::consts::PI;
+use crate::fontlib::math::cosf;
+
 #[derive(Default,Copy, Clone)]
 pub struct Rotation{
     pub(crate) angle: f32,
     pub(crate) sin: f32,
     pub(crate) cos: f32,
     pub(crate) is_rotated: bool,
-}
\ No newline at end of file
+}
+
+impl Rotation{
+    /// Rotation of `angle` degrees around the position text is printed at.
+    pub(crate) fn new(angle: f32) -> Self{
+        if angle == 0.0{
+            return Rotation{ angle, sin: 0.0, cos: 1.0, is_rotated: false }
+        }
+        let rad = angle * PI / 180.0;
+        let (sin, cos) = (cosf(rad - PI / 2.0), cosf(rad)); // sin(a) = cos(a - PI/2)
+        Rotation{ angle, sin, cos, is_rotated: !(sin == 0.0 && cos == 1.0) }
+    }
+
+    /// Rotates `point` around `origin`.
+    /// - x' = x cos θ - y sin θ
+    /// - y' = x sin θ + y cos θ
+    pub(crate) fn apply(&self, origin: (f32, f32), point: (f32, f32)) -> (f32, f32){
+        let (dx, dy) = (point.0 - origin.0, point.1 - origin.1);
+        (origin.0 + dx * self.cos - dy * self.sin, origin.1 + dx * self.sin + dy * self.cos)
+    }
+
+    /// Undoes `apply`.
+    pub(crate) fn invert(&self, origin: (f32, f32), point: (f32, f32)) -> (f32, f32){
+        let (dx, dy) = (point.0 - origin.0, point.1 - origin.1);
+        (origin.0 + dx * self.cos + dy * self.sin, origin.1 - dx * self.sin + dy * self.cos)
+    }
+}
@@ -1,6 +1,7 @@
 use crate::fontlib::helper::PGFFlags;
 
 
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub struct FontStyle{
     pub size: f32,
     pub color: FontColor,
@@ -0,0 +1,77 @@
+//! Swizzling of textures for the GU, which reads them faster in blocks of 16 bytes x 8 rows.
+//!
+//! A swizzled texture holds its blocks one after the other, row of blocks after row of blocks, and the 8 rows
+//! of 16 bytes of a block one after the other.
+
+use crate::fontlib::error::FontError;
+
+/// Size of the pixels of a texture that is swizzled.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TexelFormat{
+    /// 4 bits per pixel (the font textures).
+    T4,
+    /// 8 bits per pixel.
+    T8,
+    /// 32 bits per pixel (RGBA8888).
+    Rgba8888,
+}
+
+impl TexelFormat{
+    pub fn bits_per_pixel(self) -> usize{
+        match self{
+            TexelFormat::T4 => 4,
+            TexelFormat::T8 => 8,
+            TexelFormat::Rgba8888 => 32,
+        }
+    }
+}
+
+/// Width of a block in bytes.
+const BLOCK_WIDTH: usize = 16;
+/// Height of a block in rows.
+const BLOCK_HEIGHT: usize = 8;
+
+/// Bytes per row of a `width` x `height` texture, if it can be swizzled from `src` into `dst`.
+fn checked_row(format: TexelFormat, width: u32, height: u32, src: &[u8], dst: &[u8]) -> Result<usize, FontError>{
+    let bits = width as usize * format.bits_per_pixel();
+    let (byte_width, height_rows) = (bits / 8, height as usize);
+    let size = byte_width * height_rows;
+    if width == 0 || height == 0 || bits % (BLOCK_WIDTH * 8) != 0 || height_rows % BLOCK_HEIGHT != 0 || src.len() < size || dst.len() < size{
+        return Err(FontError::InvalidTexture { width, height })
+    }
+    Ok(byte_width)
+}
+
+/// Offset in the swizzled texture of the 16 bytes of row `y` that start at byte `x` (a multiple of 16).
+///
+/// `block_offset(byte_width, x & !15, y) + (x & 15)` is where byte `x` of row `y` goes.
+pub(crate) fn block_offset(byte_width: usize, x: usize, y: usize) -> usize{
+    ((y / BLOCK_HEIGHT) * (byte_width / BLOCK_WIDTH) + x / BLOCK_WIDTH) * BLOCK_WIDTH * BLOCK_HEIGHT + (y % BLOCK_HEIGHT) * BLOCK_WIDTH
+}
+
+/// Swizzles the `width` x `height` texture of `src` (rows one after the other) into `dst`.
+///
+/// Rows must be a multiple of 16 bytes long (32 T4 pixels, 16 T8 pixels or 4 RGBA8888 pixels) and `height` a
+/// multiple of 8, or `FontError::InvalidTexture` is returned. So is it if a buffer is smaller than the texture.
+pub fn swizzle(format: TexelFormat, width: u32, height: u32, src: &[u8], dst: &mut [u8]) -> Result<(), FontError>{
+    let byte_width = checked_row(format, width, height, src, dst)?;
+    for y in 0..height as usize{
+        for x in (0..byte_width).step_by(BLOCK_WIDTH){
+            let (from, to) = (y * byte_width + x, block_offset(byte_width, x, y));
+            dst[to..to + BLOCK_WIDTH].copy_from_slice(&src[from..from + BLOCK_WIDTH]);
+        }
+    }
+    Ok(())
+}
+
+/// Gives back the rows of the texture `src` swizzled by `swizzle`, in `dst` (same requirements as `swizzle`).
+pub fn unswizzle(format: TexelFormat, width: u32, height: u32, src: &[u8], dst: &mut [u8]) -> Result<(), FontError>{
+    let byte_width = checked_row(format, width, height, src, dst)?;
+    for y in 0..height as usize{
+        for x in (0..byte_width).step_by(BLOCK_WIDTH){
+            let (from, to) = (block_offset(byte_width, x, y), y * byte_width + x);
+            dst[to..to + BLOCK_WIDTH].copy_from_slice(&src[from..from + BLOCK_WIDTH]);
+        }
+    }
+    Ok(())
+}
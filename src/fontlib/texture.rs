@@ -1,30 +1,27 @@
 use alloc::vec::Vec;
 use alloc::alloc::{alloc, dealloc, Layout};
-use core::mem::size_of;
 use aligned_utils::bytes::AlignedBytes;
+use crate::fontlib::swizzle::{self, TexelFormat};
 
 pub( crate) struct TextureData {
     pub(crate) data: AlignedBytes,
     pub(crate) id: u32,
     pub(crate) width: u32,
     pub(crate) height: u32,
-    pub(crate) x: u16,
-    pub(crate) y: u16,
-    pub(crate) y_size: u16,
+    /// Whether the data is swizzled for the GU (pixels are still set at their (x, y) coordinates).
+    pub(crate) swizzled: bool,
 }
 
 impl TextureData{
 
-    /// Convenience function for creating new texture data
+    /// Convenience function for creating new texture data (4 bits/pixel, so two pixels per byte)
     pub fn new(width: u32, height: u32) -> Self{
         Self {
-            data: AlignedBytes::new_zeroed(size_of::<u32>()*(width as usize) * (height as usize) >> 1, 16 ),
+            data: AlignedBytes::new_zeroed((width as usize) * (height as usize) / 2, 16 ),
             id: 0,
             width,
             height,
-            x: 1,
-            y: 1,
-            y_size: 0
+            swizzled: false,
         }
     }
 
@@ -44,6 +41,52 @@ impl TextureData{
         }
     }
 
+    /// Index of the byte holding the pixel at (x, y)
+    fn byte_index(&self, x: u32, y: u32) -> usize{
+        let (byte_x, byte_width) = ((x >> 1) as usize, (self.width >> 1) as usize);
+        if self.swizzled{
+            swizzle::block_offset(byte_width, byte_x & !15, y as usize) + (byte_x & 15)
+        } else {
+            byte_x + y as usize * byte_width
+        }
+    }
+
+    /// Safely sets the 4 bit value of the pixel at (x, y)
+    pub fn set_nibble(&mut self, x: u32, y: u32, value: u8){
+        let index = self.byte_index(x, y);
+        if let Some(old) = self.get(index){
+            if x & 1 != 0{
+                self.set_at_index(index, (old & 0x0F) | (value << 4));
+            } else {
+                self.set_at_index(index, (old & 0xF0) | (value & 0x0F));
+            }
+        }
+    }
+
+    /// Clears the `width` x `height` pixels at (x, y)
+    pub fn clear_rect(&mut self, x: u32, y: u32, width: u32, height: u32){
+        for yy in y..y + height{
+            for xx in x..x + width{
+                self.set_nibble(xx, yy, 0);
+            }
+        }
+    }
+
+    /// Swizzles the texture for the GU (if it is not swizzled yet), in place: the data stays at the same address.
+    ///
+    /// `scratch` holds a copy of the data meanwhile, it is kept by the font so it is only allocated once.
+    pub fn swizzle(&mut self, scratch: &mut Vec<u8>){
+        if self.swizzled{
+            return
+        }
+        scratch.clear();
+        scratch.extend_from_slice(&self.data);
+        // font pages are square powers of two from 32 to 512 pixels, which are always made of whole blocks
+        if swizzle::swizzle(TexelFormat::T4, self.width, self.height, scratch, &mut self.data).is_ok(){
+            self.swizzled = true;
+        }
+    }
+
     /// Gets a raw pointer to the texture data
     pub unsafe fn get_data_raw_ptr(&mut self) -> *mut u8{
         (*self.data).as_mut_ptr()
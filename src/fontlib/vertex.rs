@@ -1,7 +1,7 @@
 #[no_mangle]
 #[repr(C, packed)]
-#[derive(Default, Copy, Clone)]
-pub( crate) struct FontVertex{
+#[derive(Default, Copy, Clone, Debug, PartialEq)]
+pub struct FontVertex{
     pub u: f32,
     pub v: f32,
     pub c: u32,
@@ -17,4 +17,4 @@ impl FontVertex{
     pub unsafe fn from_mut_ptr(ptr: *mut Self) -> Self{
         *ptr
     }
-}
\ No newline at end of file
+}
@@ -1,17 +1,20 @@
 #![no_std]
 #![feature(min_const_generics)]
-#![feature(llvm_asm)]
+#![cfg_attr(test, feature(test))]
+#![cfg_attr(feature = "psp", feature(llvm_asm))]
 #[macro_use]
 extern crate alloc;
 #[macro_use]
 extern crate bitflags;
-#[macro_use]
-extern crate smart_buffer;
+#[cfg(feature = "psp")]
 #[macro_use]
 extern crate psp;
 
 pub mod fontlib;
 
+#[cfg(test)]
+extern crate test;
+
 
 #[cfg(test)]
 mod tests {
@@ -19,6 +22,23 @@ mod tests {
     use alloc::vec::Vec;
     use crate::fontlib::style::{FontStyle, FontColor};
     use crate::fontlib::helper::PGFFlags;
+    use crate::fontlib::error::FontError;
+    use crate::fontlib::helper::FileType;
+    use crate::fontlib::bit_reader::BitReader;
+    use crate::fontlib::math::soft_cosf;
+    use crate::fontlib::fontlib::GlyphBitmap;
+    use crate::fontlib::canvas::{Canvas, PixelFormat};
+    use crate::fontlib::layout::LayoutOptions;
+    use crate::fontlib::ccclib::{self, Codepages, CodepageTable, DoubleByteTable, Decoder, Unmappable, UNDEFINED_CHAR};
+    use alloc::string::String;
+    use alloc::rc::Rc;
+    use crate::fontlib::char_map::{CharLookup, NO_CHAR};
+    use crate::fontlib::atlas::Atlas;
+    use crate::fontlib::charset;
+    use crate::fontlib::swizzle::{swizzle, unswizzle, TexelFormat};
+    use crate::fontlib::fontlib::{AtlasConfig, CacheStats};
+    use crate::fontlib::backend::{RecordingBackend, RenderCommand, Primitive, FontVertex, check_list_room};
+    use core::f32::consts::PI;
 
     struct Omega{
         a: u32,
@@ -42,4 +62,1034 @@ mod tests {
         // font.print(0.0,0.0,"Hello There");
 
     }
+
+    #[test]
+    fn header_too_short() {
+        let data = vec![0u8; 16];
+        assert_eq!(Font::try_new(&data, PGFFlags::NONE).err(), Some(FontError::HeaderTooShort));
+    }
+
+    #[test]
+    fn bad_magic() {
+        let mut data = vec![0u8; 0x200];
+        data[0x4..0x8].copy_from_slice(b"ABCD");
+        assert_eq!(Font::try_new(&data, PGFFlags::NONE).err(), Some(FontError::BadMagic(*b"ABCD")));
+    }
+
+    #[test]
+    fn bad_table_sizes() {
+        let mut data = pgf_fixture();
+        data[0x18..0x1C].copy_from_slice(&40u32.to_le_bytes()); // charmap_bpe
+        assert_eq!(Font::try_new(&data, PGFFlags::NONE).err(), Some(FontError::InvalidBitWidth { table: "charmap", bits: 40 }));
+        data[0x18..0x1C].copy_from_slice(&16u32.to_le_bytes());
+        data[0x1C..0x20].copy_from_slice(&0u32.to_le_bytes()); // charptr_bpe
+        assert_eq!(Font::try_new(&data, PGFFlags::NONE).err(), Some(FontError::InvalidBitWidth { table: "char pointer table", bits: 0 }));
+        data[0x1C..0x20].copy_from_slice(&16u32.to_le_bytes());
+        data[0x10..0x14].copy_from_slice(&u32::MAX.to_le_bytes()); // charmap_len
+        assert!(matches!(Font::try_new(&data, PGFFlags::NONE).err(), Some(FontError::TableOutOfBounds { table: "charmap", .. })));
+        data[0x10..0x14].copy_from_slice(&4u32.to_le_bytes());
+        data[0x105] = u8::MAX; // advance_table_len, past the end of the file
+        assert!(matches!(Font::try_new(&data, PGFFlags::NONE).err(), Some(FontError::TableOutOfBounds { table: "advance table", .. })));
+        data[0x105] = 1;
+        data[0x16C..0x170].copy_from_slice(&u32::MAX.to_le_bytes()); // shadowmap_len
+        assert!(matches!(Font::try_new(&data, PGFFlags::NONE).err(), Some(FontError::TableOutOfBounds { table: "shadow charmap", .. })));
+    }
+
+    /// A tiny BWFON font with glyphs for 'A', 'B' and 'C' (glyph n has its n-th row filled).
+    fn bwfon_fixture() -> Vec<u8> {
+        let mut data = vec![0u8; 3 * 36];
+        for n in 0..3 {
+            data[n * 36 + n * 2] = 0xFF;
+            data[n * 36 + n * 2 + 1] = 0xFF;
+        }
+        data
+    }
+
+    #[test]
+    fn bwfon_detection() {
+        // the firmware jpn0.bwfon (28427 glyphs) is recognized by its size, but has no charmap of its own
+        let data = vec![0u8; 28427 * 36];
+        assert_eq!(FileType::detect(&data), Some(FileType::BWFON));
+        assert_eq!(Font::try_new(&data, PGFFlags::NONE).err(), Some(FontError::MissingCharmap));
+        assert_eq!(Font::try_new_bwfon(&data, &[0x4E00, 28427], PGFFlags::NONE).unwrap().info().glyph_count, 28427);
+
+        // other sizes are not detected, even if they are made of whole glyphs (they still load with a charmap)
+        assert_eq!(FileType::detect(&bwfon_fixture()), None);
+        assert_eq!(FileType::detect(&data[..28426 * 36]), None);
+        assert_eq!(Font::try_new(&data[..28426 * 36], PGFFlags::NONE).err(), Some(FontError::BadMagic([0; 4])));
+    }
+
+    #[test]
+    fn bwfon_loading() {
+        let data = bwfon_fixture();
+        assert!(Font::try_new_bwfon(&data, &[0x41, 3], PGFFlags::NONE).is_ok());
+        // more glyphs in the charmap than in the file
+        assert_eq!(Font::try_new_bwfon(&data, &[0x41, 4], PGFFlags::NONE).err(),
+                   Some(FontError::TableOutOfBounds { table: "charmap", offset: 4 }));
+        // truncated glyph bitmaps
+        assert!(Font::try_new_bwfon(&data[..100], &[0x41, 2], PGFFlags::NONE).is_err());
+    }
+
+    #[test]
+    fn bit_reader() {
+        let data = [0b1010_1100u8, 0xFF, 0x00, 0x7F, 0x12, 0x34, 0x56, 0x78, 0x9A];
+        // reference: read one bit at a time, least significant bit first
+        let bit = |i: usize| ((data[i / 8] >> (i % 8)) & 1) as u32;
+        for bits in 1..=32u32 {
+            for start in 0..(data.len() * 8 - bits as usize) {
+                let mut reader = BitReader::at(&data, start, "test").unwrap();
+                let expected = (0..bits as usize).fold(0, |v, i| v | bit(start + i) << i);
+                assert_eq!(reader.read_bits(bits), Ok(expected));
+                assert_eq!(reader.position(), start + bits as usize);
+            }
+        }
+        let mut reader = BitReader::new(&data, "test");
+        reader.skip(3).unwrap();
+        assert_eq!(reader.read_i7(), Ok(-11)); // 0b111_0101 is 117, which is -11 in 7 bits
+        assert_eq!(reader.read_bits(2), Ok(0b11));
+        reader.seek(data.len() * 8 - 4).unwrap();
+        assert_eq!(reader.read_bits(5), Err(FontError::TableOutOfBounds { table: "test", offset: data.len() }));
+        assert!(reader.seek(data.len() * 8 + 1).is_err());
+        reader.seek(0).unwrap();
+        assert_eq!(reader.read_bits(33), Err(FontError::InvalidBitWidth { table: "test", bits: 33 }));
+    }
+
+    /// Writes the `bits` lowest bits of `value` at the bit `position` of `data` (least significant bit first).
+    fn put_bits(data: &mut Vec<u8>, position: usize, value: u32, bits: usize) {
+        for i in 0..bits {
+            let p = position + i;
+            if p / 8 >= data.len() {
+                data.resize(p / 8 + 1, 0);
+            }
+            data[p / 8] |= (((value >> i) & 1) as u8) << (p % 8);
+        }
+    }
+
+    /// Compresses 4 bit pixels with the PGF RLE scheme (uses both repeated and literal runs).
+    fn rle(pixels: &[u8]) -> Vec<u8> {
+        let mut nibbles = Vec::new();
+        let mut i = 0;
+        while i < pixels.len() {
+            let repeat = pixels[i..].iter().take(8).take_while(|p| **p == pixels[i]).count();
+            if repeat >= 2 {
+                nibbles.push(repeat as u8 - 1);
+                nibbles.push(pixels[i]);
+                i += repeat;
+            } else {
+                let mut literal = 1;
+                while literal < 8 && i + literal < pixels.len()
+                    && (i + literal + 1 >= pixels.len() || pixels[i + literal] != pixels[i + literal + 1]) {
+                    literal += 1;
+                }
+                nibbles.push(16 - literal as u8);
+                nibbles.extend_from_slice(&pixels[i..i + literal]);
+                i += literal;
+            }
+        }
+        let mut data = Vec::new();
+        for (n, nibble) in nibbles.iter().enumerate() {
+            put_bits(&mut data, n * 4, *nibble as u32, 4);
+        }
+        data
+    }
+
+    /// 5x3 glyph used for 'A' (horizontal rows) and 'B' (vertical rows), row after row.
+    const PGF_PIXELS: [u8; 15] = [0x0, 0xF, 0xF, 0xF, 0x0, 0x1, 0x2, 0x3, 0x4, 0x5, 0x7, 0x7, 0x7, 0x7, 0x7];
+    /// 3x2 shadow glyph shared by every char, row after row.
+    const PGF_SHADOW: [u8; 6] = [0x2, 0x5, 0x8, 0x8, 0x5, 0x2];
+
+    /// A tiny revision 2 PGF font covering 'A' to 'D':
+    /// - 'A' is `PGF_PIXELS` stored in horizontal rows (left 1, top 3).
+    /// - 'B' is `PGF_PIXELS` stored in vertical rows (left -1, top 2).
+    /// - 'C' is not in the font.
+    /// - 'D' is an empty glyph (like a space).
+    /// Every char advances by 6 pixels and uses the same shadow glyph, lines are 8 pixels high.
+    fn pgf_fixture() -> Vec<u8> {
+        const BLOCK: usize = 64; // bytes used by every glyph in the font data
+        let h_rows = PGFFlags::BMP_HORIZONTAL_ROWS.bits();
+        let v_rows = PGFFlags::BMP_VERTICAL_ROWS.bits();
+        let column_major: Vec<u8> = (0..15).map(|i| PGF_PIXELS[(i % 3) * 5 + i / 3]).collect();
+        // (width, height, left, top, flags, bitmap)
+        let glyphs = [
+            (5, 3, 1, 3, h_rows, rle(&PGF_PIXELS)),
+            (5, 3, -1, 2, v_rows, rle(&column_major)),
+            (0, 0, 0, 0, h_rows, Vec::new()),
+        ];
+
+        let mut font_data = Vec::new();
+        for (n, (width, height, left, top, flags, bitmap)) in glyphs.iter().enumerate() {
+            let base = n * BLOCK * 8;
+            let metric = [(*width, 7), (*height, 7), (*left as u32 & 0x7F, 7), (*top as u32 & 0x7F, 7),
+                (*flags | 0x1C, 6), (0, 7), (0, 9), (0, 24), (0, 8)]; // no extra metrics, shadow 0, advance 0
+            let shadow_jump = if n == 0 { 11 + 16 } else { 0 }; // the shadow is stored after the bitmap of 'A'
+            put_bits(&mut font_data, base, shadow_jump, 14);
+            let mut position = base + 14;
+            for (value, bits) in metric.iter() {
+                put_bits(&mut font_data, position, *value, *bits);
+                position += bits;
+            }
+            for (i, byte) in bitmap.iter().enumerate() {
+                put_bits(&mut font_data, position + i * 8, *byte as u32, 8);
+            }
+            if n == 0 {
+                let mut position = base + 14 + shadow_jump as usize * 8;
+                for (value, bits) in [(3, 7), (2, 7), (0, 7), (1, 7), (h_rows | 0x1C, 6)].iter() {
+                    put_bits(&mut font_data, position, *value, *bits);
+                    position += bits;
+                }
+                for (i, byte) in rle(&PGF_SHADOW).iter().enumerate() {
+                    put_bits(&mut font_data, position + i * 8, *byte as u32, 8);
+                }
+            }
+            font_data.resize((n + 1) * BLOCK, 0);
+        }
+
+        let mut data = vec![0u8; PGFHeader::HEADER_SIZE];
+        data[0x2..0x4].copy_from_slice(&(PGFHeader::HEADER_SIZE as u16).to_le_bytes());
+        data[0x4..0x8].copy_from_slice(b"PGF0");
+        data[0x8..0xC].copy_from_slice(&2u32.to_le_bytes()); // revision
+        data[0x10..0x14].copy_from_slice(&4u32.to_le_bytes()); // charmap_len
+        data[0x14..0x18].copy_from_slice(&3u32.to_le_bytes()); // charptr_len
+        data[0x18..0x1C].copy_from_slice(&16u32.to_le_bytes()); // charmap_bpe
+        data[0x1C..0x20].copy_from_slice(&16u32.to_le_bytes()); // charptr_bpe
+        data[0xB6..0xB8].copy_from_slice(&0x41u16.to_le_bytes()); // charmap_min
+        data[0xB8..0xBA].copy_from_slice(&4u16.to_le_bytes()); // number of chars after charmap_min
+        data[0xE0..0xE4].copy_from_slice(&(3u32 * 64).to_le_bytes()); // max glyph top
+        data[0xEC..0xF0].copy_from_slice(&(6u32 * 64).to_le_bytes());
+        data[0xF0..0xF4].copy_from_slice(&(8u32 * 64).to_le_bytes());
+        data[0xF8..0xFC].copy_from_slice(&(4u32 * 64).to_le_bytes()); // max glyph height
+        data[0x105] = 1; // advance_table_len
+        data[0x16C..0x170].copy_from_slice(&1u32.to_le_bytes()); // shadowmap_len
+        data[0x170..0x174].copy_from_slice(&16u32.to_le_bytes()); // shadowmap_bpe
+        data[0x178..0x17C].copy_from_slice(&24u32.to_le_bytes());
+        data[0x17C..0x180].copy_from_slice(&24u32.to_le_bytes());
+
+        data.extend_from_slice(&(6i32 * 64).to_le_bytes()); // advance table
+        data.extend_from_slice(&0i32.to_le_bytes());
+        data.extend_from_slice(&0x41u16.to_le_bytes()); // shadow charmap
+        for id in [0u16, 1, 0xFFFF, 2].iter() { // charmap
+            data.extend_from_slice(&id.to_le_bytes());
+        }
+        for n in 0..4u16 { // char pointer table (padded to 32 bits)
+            data.extend_from_slice(&(n * BLOCK as u16 / 4).to_le_bytes());
+        }
+        data.extend_from_slice(&font_data);
+        data
+    }
+
+    #[test]
+    fn rasterize_pgf_rows() {
+        let data = pgf_fixture();
+        let font = Font::try_new(&data, PGFFlags::NONE).unwrap();
+        let expected: Vec<u8> = PGF_PIXELS.iter().map(|p| p * 17).collect();
+
+        let a = font.rasterize_glyph('A').unwrap();
+        assert_eq!(a, GlyphBitmap { width: 5, height: 3, left: 1, top: 3, advance: 6.0, scale: 1.0, data: expected.clone() });
+        assert_eq!(a.pixel(1, 0), 0xFF);
+        assert_eq!(a.pixel(4, 1), 0x55);
+        assert_eq!(a.pixel(5, 0), 0);
+        assert_eq!(&a.to_4bpp()[..3], &[0xF0, 0xFF, 0x10]);
+
+        // same pixels, stored column after column
+        let b = font.rasterize_glyph('B').unwrap();
+        assert_eq!((b.left, b.top), (-1, 2));
+        assert_eq!(b.data, expected);
+
+        let d = font.rasterize_glyph('D').unwrap();
+        assert_eq!((d.width, d.height, d.advance), (0, 0, 6.0));
+        assert!(d.data.is_empty());
+
+        assert_eq!(font.rasterize_glyph('C'), None);
+        assert_eq!(font.rasterize_glyph('Z'), None);
+        assert_eq!(font.rasterize_glyph('\u{1F600}'), None);
+    }
+
+    #[test]
+    fn rasterize_pgf_shadow() {
+        let data = pgf_fixture();
+        let font = Font::try_new(&data, PGFFlags::NONE).unwrap();
+        let shadow = font.rasterize_shadow('B').unwrap();
+        assert_eq!(shadow, GlyphBitmap {
+            width: 3, height: 2, left: 0, top: 1, advance: 0.0, scale: 64.0 / 24.0,
+            data: PGF_SHADOW.iter().map(|p| p * 17).collect(),
+        });
+        assert_eq!(font.rasterize_shadow('C'), None);
+    }
+
+    #[test]
+    fn rasterize_bwfon() {
+        let mut data = bwfon_fixture();
+        data[0] = 0x80; // leftmost pixel of the first row of 'A'
+        data[1] = 0x01; // rightmost pixel of the first row of 'A'
+        let font = Font::try_new_bwfon(&data, &[0x41, 3], PGFFlags::NONE).unwrap();
+
+        let a = font.rasterize_glyph('A').unwrap();
+        assert_eq!((a.width, a.height, a.advance), (16, 18, 16.0));
+        assert_eq!(a.data.iter().filter(|p| **p != 0).count(), 2);
+        assert_eq!((a.pixel(0, 0), a.pixel(15, 0)), (0xFF, 0xFF));
+
+        let b = font.rasterize_glyph('B').unwrap();
+        for y in 0..18 {
+            for x in 0..16 {
+                assert_eq!(b.pixel(x, y), if y == 1 { 0xFF } else { 0 });
+            }
+        }
+
+        assert_eq!(font.rasterize_shadow('C'), None); // BWFON files have no shadows
+        assert_eq!(font.rasterize_glyph('D'), None);
+    }
+
+    /// Style used to render the fixtures: white text without shadows at scale 1.
+    fn render_style(options: PGFFlags) -> FontStyle {
+        FontStyle { size: 1.0, color: FontColor::WHITE, shadow_color: FontColor::empty(), angle: 0.0, options }
+    }
+
+    /// Expected A8 image of `PGF_PIXELS` glyphs drawn at scale 1, with their top left corner at `positions`.
+    fn golden(width: usize, height: usize, positions: &[(usize, usize)]) -> Vec<u8> {
+        let mut image = vec![0u8; width * height];
+        for (x, y) in positions.iter() {
+            for (i, pixel) in PGF_PIXELS.iter().enumerate() {
+                image[(x + i % 5) + (y + i / 5) * width] = pixel * 17;
+            }
+        }
+        image
+    }
+
+    #[test]
+    fn render_golden() {
+        let data = pgf_fixture();
+        let font = Font::try_new(&data, PGFFlags::NONE).unwrap();
+        let mut canvas = Canvas::new(20, 8, PixelFormat::A8);
+        // 'A' is 1 pixel right of the pen, 'D' is empty and 'B' starts 1 pixel left of the pen
+        assert_eq!(font.render_to_buffer(&mut canvas, 2.0, 5.0, "ADB", &render_style(PGFFlags::NONE)), 20.0);
+        let mut expected = golden(20, 8, &[(3, 2)]);
+        for (i, pixel) in PGF_PIXELS.iter().enumerate() {
+            expected[(13 + i % 5) + (3 + i / 5) * 20] = pixel * 17;
+        }
+        assert_eq!(canvas.data(), &expected[..]);
+    }
+
+    #[test]
+    fn render_alignment() {
+        let data = pgf_fixture();
+        let font = Font::try_new(&data, PGFFlags::NONE).unwrap();
+
+        let mut canvas = Canvas::new(12, 8, PixelFormat::A8);
+        assert_eq!(font.render_to_buffer(&mut canvas, 10.0, 5.0, "A", &render_style(PGFFlags::ALIGN_RIGHT)), 10.0);
+        assert_eq!(canvas.data(), &golden(12, 8, &[(5, 2)])[..]);
+
+        let mut canvas = Canvas::new(12, 16, PixelFormat::A8);
+        assert_eq!(font.render_to_buffer(&mut canvas, 6.0, 5.0, "A\nAA", &render_style(PGFFlags::ALIGN_CENTER)), 12.0);
+        assert_eq!(canvas.data(), &golden(12, 16, &[(4, 2), (1, 10), (7, 10)])[..]);
+    }
+
+    #[test]
+    fn render_column() {
+        let data = pgf_fixture();
+        let font = Font::try_new(&data, PGFFlags::NONE).unwrap();
+        let mut canvas = Canvas::new(20, 16, PixelFormat::A8);
+        // only 2 chars fit in 13 pixels, the third one goes on the next line
+        assert_eq!(font.render_column_to_buffer(&mut canvas, 0.0, 5.0, 13.0, "AAA", &render_style(PGFFlags::NONE)), 6.0);
+        assert_eq!(canvas.data(), &golden(20, 16, &[(1, 2), (7, 2), (1, 10)])[..]);
+
+        // scrolling text stays on one line, clipped to the column
+        let mut canvas = Canvas::new(20, 16, PixelFormat::A8);
+        font.render_column_to_buffer(&mut canvas, 0.0, 5.0, 13.0, "AA\nA", &render_style(PGFFlags::SCROLL_LEFT));
+        let mut expected = golden(20, 16, &[(1, 2), (7, 2)]);
+        for y in 0..16 {
+            expected[(13 + y * 20)..(14 + y * 20)].iter_mut().for_each(|p| *p = 0);
+        }
+        assert_eq!(canvas.data(), &expected[..]);
+    }
+
+    #[test]
+    fn render_color_and_shadow() {
+        let data = pgf_fixture();
+        let font = Font::try_new(&data, PGFFlags::NONE).unwrap();
+        let mut style = render_style(PGFFlags::NONE);
+        style.color = FontColor::RED;
+
+        let mut canvas = Canvas::new(16, 12, PixelFormat::Rgba8888);
+        font.render_to_buffer(&mut canvas, 4.0, 6.0, "A", &style);
+        assert_eq!(canvas.pixel(6, 3), Some(FontColor::RED.bits()));
+        assert_eq!(canvas.pixel(4, 4), Some(0));
+        assert_eq!(&canvas.data()[(6 + 3 * 16) * 4..(7 + 3 * 16) * 4], &[0xFF, 0, 0, 0xFF]);
+
+        // the shadow is drawn below the glyph, and is scaled up by 64 / shadow scale
+        style.shadow_color = FontColor::BLACK;
+        let mut canvas = Canvas::new(16, 12, PixelFormat::Rgba8888);
+        font.render_to_buffer(&mut canvas, 4.0, 6.0, "A", &style);
+        assert_eq!(canvas.pixel(6, 3), Some(FontColor::RED.bits()));
+        let shadow = canvas.pixel(4, 4).unwrap();
+        assert_eq!(shadow & 0xFFFFFF, 0);
+        assert!(shadow >> 24 > 0);
+        assert_eq!(canvas.pixel(12, 8), Some(0)); // the shadow is 8 pixels wide
+    }
+
+    #[test]
+    fn render_scaled_and_rotated() {
+        let data = pgf_fixture();
+        let font = Font::try_new(&data, PGFFlags::NONE).unwrap();
+        let mut style = render_style(PGFFlags::NONE);
+        style.size = 2.0;
+        let mut canvas = Canvas::new(20, 20, PixelFormat::A8);
+        assert_eq!(font.render_to_buffer(&mut canvas, 0.0, 10.0, "A", &style), 12.0);
+        // the glyph covers 10x6 pixels from (2, 4)
+        for y in 0..20 {
+            for x in 0..20 {
+                let inside = (2..12).contains(&x) && (4..10).contains(&y);
+                assert!(inside || canvas.data()[x + y * 20] == 0, "({}, {}) is outside of the glyph", x, y);
+            }
+        }
+        assert!(canvas.data()[4 + 4 * 20] > 0 && canvas.data()[11 + 9 * 20] > 0);
+
+        // half a turn around (10, 10) flips the glyph in both directions
+        let mut upright = Canvas::new(20, 20, PixelFormat::A8);
+        font.render_to_buffer(&mut upright, 10.0, 10.0, "A", &render_style(PGFFlags::NONE));
+        let mut style = render_style(PGFFlags::NONE);
+        style.angle = 180.0;
+        let mut rotated = Canvas::new(20, 20, PixelFormat::A8);
+        font.render_to_buffer(&mut rotated, 10.0, 10.0, "A", &style);
+        for y in 0..20 {
+            for x in 0..20 {
+                let (a, b) = (rotated.data()[x + y * 20] as i32, upright.data()[(19 - x) + (19 - y) * 20] as i32);
+                assert!((a - b).abs() <= 1, "({}, {}): {} != {}", x, y, a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn recording_backend() {
+        let data = pgf_fixture();
+        let mut font = Font::try_new(&data, PGFFlags::NONE).unwrap();
+        let mut backend = RecordingBackend::new();
+        assert_eq!(font.print_to(&mut backend, 10.0, 20.0, "AB"), Ok(22.0));
+
+        assert!(matches!(backend.commands[0], RenderCommand::LoadClut(clut) if clut[0] == 0x00FFFFFF && clut[15] == 0xFFFFFFFF));
+        assert_eq!(backend.commands[1], RenderCommand::UploadTexture { width: 256, height: 256, swizzled: false });
+        let draws: Vec<_> = backend.draws().collect();
+        assert_eq!(draws.len(), 1);
+        let (primitive, vertices) = draws[0];
+        assert_eq!(primitive, Primitive::Sprites);
+        assert_eq!(vertices.len(), 8); // shadows first, then glyphs, 2 vertices each
+
+        // 'A' (left 1, top 3) and 'B' (left -1, top 2) on the baseline at y = 20
+        let corners = |n: usize| ({ vertices[n].x }, { vertices[n].y }, { vertices[n + 1].x }, { vertices[n + 1].y });
+        assert_eq!(corners(4), (11.0, 17.0, 16.0, 20.0));
+        assert_eq!(corners(6), (15.0, 18.0, 20.0, 21.0));
+        // the shadow is scaled by 64 / 24 and starts on the pen position
+        let (xl, yu, xr, yd) = corners(0);
+        assert_eq!(xl, 10.0);
+        assert!((yu - (20.0 - 64.0 / 24.0)).abs() < 1e-4 && (xr - (10.0 + 3.0 * 64.0 / 24.0)).abs() < 1e-4 && (yd - (yu + 2.0 * 64.0 / 24.0)).abs() < 1e-4);
+        // UVs cover the glyph in the texture, with a quarter texel of margin
+        assert_eq!({ vertices[5].u } - { vertices[4].u }, 5.5);
+        assert_eq!({ vertices[5].v } - { vertices[4].v }, 3.5);
+        assert_eq!(({ vertices[0].c }, { vertices[4].c }), (FontColor::BLACK.bits(), FontColor::WHITE.bits()));
+    }
+
+    #[test]
+    fn recording_backend_rotated() {
+        let data = pgf_fixture();
+        let mut font = Font::try_new(&data, PGFFlags::NONE).unwrap();
+        font.set_style(FontStyle { size: 1.0, color: FontColor::RED, shadow_color: FontColor::BLACK, angle: 90.0, options: PGFFlags::NONE });
+        let mut backend = RecordingBackend::new();
+        font.print_to(&mut backend, 10.0, 20.0, "A").unwrap();
+        let (primitive, vertices) = backend.draws().next().unwrap();
+        assert_eq!(primitive, Primitive::Triangles);
+        assert_eq!(vertices.len(), 12);
+        // the up-left corner of 'A' (11, 17) turns a quarter around (10, 20)
+        let up_left = vertices[6];
+        assert!(({ up_left.x } - 13.0).abs() < 1e-4 && ({ up_left.y } - 21.0).abs() < 1e-4);
+        assert_eq!(({ vertices[10].x }, { vertices[11].y }), ({ vertices[6].x }, { vertices[8].y }));
+        assert_eq!({ up_left.c }, FontColor::RED.bits());
+    }
+
+    #[test]
+    fn print_chars_and_ucs2() {
+        let data = pgf_fixture();
+        let mut font = Font::try_new(&data, PGFFlags::NONE).unwrap();
+        let mut expected = RecordingBackend::new();
+        font.print_to(&mut expected, 10.0, 20.0, "AB").unwrap();
+
+        let mut backend = RecordingBackend::new();
+        assert_eq!(font.print_chars_to(&mut backend, 10.0, 20.0, "AB".chars()), Ok(22.0));
+        assert_eq!(backend.commands, expected.commands);
+        backend.clear();
+        assert_eq!(font.print_ucs2_to(&mut backend, 10.0, 20.0, &['A' as u16, 'B' as u16]), Ok(22.0));
+        assert_eq!(backend.commands, expected.commands);
+
+        // chars from an iterator, with a char outside of the BMP replaced by 'B'
+        font.set_replacement_char('B' as u16);
+        backend.clear();
+        assert_eq!(font.print_chars_to(&mut backend, 10.0, 20.0, ['A', '😀'].iter().copied()), Ok(22.0));
+        assert_eq!(backend.commands, expected.commands);
+    }
+
+    #[test]
+    fn text_cursor() {
+        use core::fmt::Write;
+        let data = pgf_fixture();
+        let mut font = Font::try_new(&data, PGFFlags::NONE).unwrap();
+        let mut expected = RecordingBackend::new();
+        font.print_to(&mut expected, 10.0, 20.0, "AB\nBA").unwrap();
+
+        let mut backend = RecordingBackend::new();
+        {
+            let mut cursor = font.cursor_to(&mut backend, 10.0, 20.0);
+            write!(cursor, "A{}\n{}", 'B', "BA").unwrap();
+            assert_eq!(cursor.position(), (22.0, 28.0));
+            assert_eq!(cursor.finish(), Ok(22.0));
+        }
+        assert_eq!(backend.commands, expected.commands);
+        assert_eq!(backend.draws().count(), 1);
+
+        // dropping the cursor draws too
+        backend.clear();
+        write!(font.cursor_to(&mut backend, 10.0, 20.0), "AB\nBA").unwrap();
+        assert_eq!(backend.commands, expected.commands);
+    }
+
+    #[test]
+    fn out_of_vertex_memory() {
+        let data = pgf_fixture();
+        let mut font = Font::try_new(&data, PGFFlags::NONE).unwrap();
+        let mut backend = RecordingBackend::with_vertex_budget(8);
+        assert_eq!(font.print_to(&mut backend, 0.0, 10.0, "AB"), Ok(12.0));
+        assert_eq!(font.print_to(&mut backend, 0.0, 10.0, "A"), Err(FontError::OutOfVertexMemory(4 * core::mem::size_of::<FontVertex>())));
+        assert_eq!(backend.draws().count(), 1);
+
+        // room in a display list (sceGuGetMemory rounds to 4 bytes and adds a jump, the draw needs some commands)
+        assert_eq!(check_list_room(64 + 8 + 12, 10), Ok(()));
+        assert_eq!(check_list_room(64 + 8 + 11, 10), Err(FontError::OutOfVertexMemory(10)));
+        assert_eq!(check_list_room(0, 0), Err(FontError::OutOfVertexMemory(0)));
+    }
+
+    #[test]
+    fn layout_lines() {
+        let data = pgf_fixture();
+        let mut font = Font::try_new(&data, PGFFlags::NONE).unwrap();
+        let options = LayoutOptions { x: 10.0, y: 20.0, column: 0.0, style: render_style(PGFFlags::NONE) };
+        let layout = font.layout("é B\nA", &options);
+        // 'é' and ' ' are not in the font: they are skipped
+        assert_eq!(layout.lines.len(), 2);
+        assert_eq!((layout.lines[0].left, layout.lines[0].right, layout.lines[0].baseline), (10.0, 16.0, 20.0));
+        assert_eq!((layout.lines[1].baseline, layout.lines[1].height), (28.0, 8.0));
+        assert_eq!((layout.lines[0].source.clone(), layout.lines[1].source.clone()), (0..4, 4..6));
+        assert_eq!((layout.lines[0].glyphs.clone(), layout.lines[1].glyphs.clone()), (0..2, 2..4));
+        assert_eq!(layout.end, 16.0);
+
+        let b = &layout.glyphs[0];
+        assert_eq!((b.glyph_id, b.shadow_id, b.fallback, b.source_index), (1, None, 0, 3));
+        assert_eq!(b.rect, (9.0, 18.0, 14.0, 21.0));
+        assert_eq!((b.uv.2 - b.uv.0, b.uv.3 - b.uv.1), (5.5, 3.5));
+        assert_eq!(b.color, FontColor::WHITE);
+        assert!(layout.glyphs[1].is_shadow() && layout.glyphs[1].source_index == 3);
+        let a = &layout.glyphs[2];
+        assert_eq!((a.glyph_id, a.source_index, a.rect), (0, 5, (11.0, 25.0, 16.0, 28.0)));
+
+        assert_eq!(layout.glyph_at(12.0, 26.0).map(|glyph| glyph.source_index), Some(5));
+        assert_eq!(layout.glyph_at(12.0, 22.0), None);
+        assert_eq!(layout.line_at(22.0).map(|line| line.source.clone()), Some(4..6));
+    }
+
+    #[test]
+    fn layout_column_and_alignment() {
+        let data = pgf_fixture();
+        let mut font = Font::try_new(&data, PGFFlags::NONE).unwrap();
+        let options = LayoutOptions { x: 0.0, y: 10.0, column: 13.0, style: render_style(PGFFlags::NONE) };
+        let layout = font.layout("ABAB", &options);
+        assert_eq!(layout.lines.iter().map(|line| line.source.clone()).collect::<Vec<_>>(), vec![0..2, 2..4]);
+        assert_eq!(layout.lines.iter().map(|line| line.baseline).collect::<Vec<_>>(), vec![10.0, 18.0]);
+
+        let options = LayoutOptions { x: 20.0, y: 10.0, column: 0.0, style: render_style(PGFFlags::ALIGN_CENTER) };
+        let layout = font.layout("AB", &options);
+        assert_eq!((layout.lines[0].left, layout.lines[0].right), (14.0, 26.0));
+        let options = LayoutOptions { x: 20.0, y: 10.0, column: 0.0, style: render_style(PGFFlags::ALIGN_RIGHT) };
+        assert_eq!(font.layout("AB", &options).glyphs[0].rect.0, 9.0);
+    }
+
+    #[test]
+    fn measure_text() {
+        let data = pgf_fixture();
+        let font = Font::try_new(&data, PGFFlags::NONE).unwrap();
+        let metrics = font.measure("AB\nA", None);
+        assert_eq!((metrics.line_count, metrics.line_widths.clone(), metrics.width), (2, vec![12.0, 6.0], 12.0));
+        // 'A' goes 3 pixels above the baseline, 'B' 1 pixel below it
+        assert_eq!((metrics.ascent, metrics.descent, metrics.line_height), (3.0, 1.0, 8.0));
+        assert_eq!(metrics.height, 3.0 + 8.0 + 1.0);
+        let (left, up, right, down) = metrics.ink.unwrap();
+        assert_eq!((left, up), (0.0, -3.0)); // the shadow of 'A' starts on the pen position
+        assert!(right >= 11.0 && down >= 9.0);
+
+        // same line breaks as drawing
+        let wrapped = font.measure("ABAB", Some(13.0));
+        assert_eq!((wrapped.line_count, wrapped.line_widths.clone()), (2, vec![12.0, 12.0]));
+        let options = LayoutOptions { x: 0.0, y: 0.0, column: 13.0, style: font.style() };
+        let mut font = font;
+        assert_eq!(font.layout("ABAB", &options).lines.len(), wrapped.line_count);
+
+        assert_eq!(font.measure("", None), crate::fontlib::layout::TextMetrics { ascent: 3.0, descent: 1.0, line_height: 8.0, ..Default::default() });
+    }
+
+    #[test]
+    fn lazy_metrics() {
+        let data = pgf_fixture();
+        let mut eager = Font::try_new(&data, PGFFlags::NONE).unwrap();
+        let mut font = Font::try_new(&data, PGFFlags::LAZY_METRICS).unwrap();
+        assert_eq!((font.face().glyphs.decoded().len(), font.face().shadow_glyphs.decoded().len()), (0, 0));
+        assert_eq!(font.face().charmap_data.char_ptr_table.len(), eager.face().n_chars as usize); // kept to decode glyphs later
+
+        // only 'A' is decoded, and it measures like the eager font, the extents come from the header
+        let (measured, expected) = (font.measure("AA", None), eager.measure("AA", None));
+        assert_eq!((measured.width, measured.ascent, measured.descent), (expected.width, expected.ascent, expected.descent));
+        assert_eq!((expected.ascent, expected.descent), (3.0, 1.0));
+        assert_eq!(font.face().glyphs.decoded().len(), 1);
+
+        let mut expected = RecordingBackend::new();
+        eager.print_to(&mut expected, 10.0, 20.0, "AB").unwrap();
+        let mut backend = RecordingBackend::new();
+        font.print_to(&mut backend, 10.0, 20.0, "AB").unwrap();
+        assert_eq!(backend.commands, expected.commands);
+        assert_eq!((font.face().glyphs.decoded().len(), font.face().shadow_glyphs.decoded().len()), (2, 1));
+        assert_eq!(font.rasterize_glyph('B'), eager.rasterize_glyph('B'));
+    }
+
+    #[test]
+    fn owned_font_data() {
+        let data = pgf_fixture();
+        let mut borrowed = Font::try_new(&data, PGFFlags::CACHE_ASCII).unwrap();
+        assert!(!borrowed.drop_glyph_data());
+        let shared: Rc<[u8]> = Rc::from(&data[..]);
+        let mut font = Font::try_new(shared.clone(), PGFFlags::CACHE_ASCII).unwrap();
+        assert!(!font.drop_glyph_data()); // `shared` still holds the data
+        let mut lazy: Font<'static> = Font::try_new(data.clone(), PGFFlags::CACHE_ASCII | PGFFlags::LAZY_METRICS).unwrap();
+        assert!(!lazy.drop_glyph_data());
+        let mut uncached: Font<'static> = Font::try_new(data.clone(), PGFFlags::NONE).unwrap();
+        assert!(!uncached.drop_glyph_data());
+
+        let mut font: Font<'static> = Font::try_new(data.clone(), PGFFlags::CACHE_ASCII).unwrap();
+        let mut expected = RecordingBackend::new();
+        font.print_to(&mut expected, 10.0, 20.0, "AB").unwrap();
+        assert!(font.rasterize_glyph('A').is_some() && font.rasterize_shadow('A').is_some());
+        assert!(font.drop_glyph_data());
+        assert!(font.face().font_data.is_empty()); // no overlay glyphs
+        assert_eq!(font.face().shadow_glyph(0).map(|shadow| shadow.offset), Some(0)); // past the end, like glyphs
+        let mut backend = RecordingBackend::new();
+        font.print_to(&mut backend, 10.0, 20.0, "AB").unwrap();
+        assert_eq!(backend.commands, expected.commands);
+        assert!(font.rasterize_glyph('A').is_none() && font.rasterize_shadow('A').is_none());
+    }
+
+    #[test]
+    fn shared_face() {
+        let data = pgf_fixture();
+        let mut font = Font::try_new(&data, PGFFlags::NONE).unwrap();
+        let mut red = font.with_style(FontStyle { size: 2.0, color: FontColor::RED, shadow_color: FontColor::BLACK, angle: 0.0, options: PGFFlags::NONE });
+        assert!(Rc::ptr_eq(&font.shared_face(), &red.shared_face()));
+
+        let mut backend = RecordingBackend::new();
+        assert_eq!(font.print_to(&mut backend, 10.0, 20.0, "AB"), Ok(22.0));
+        let texture = font.face().textures[0].data.to_vec();
+        assert_eq!(red.print_to(&mut backend, 10.0, 20.0, "AB"), Ok(34.0));
+        assert_eq!(red.face().textures[0].data.to_vec(), texture); // the glyphs were already cached by `font`
+        let colors: Vec<u32> = backend.draws().map(|(_, vertices)| vertices[vertices.len() - 1].c).collect();
+        assert_eq!(colors, [FontColor::WHITE.bits(), FontColor::RED.bits()]);
+        assert_eq!((font.style().color, red.style().size), (FontColor::WHITE, 2.0));
+    }
+
+    #[test]
+    fn fallback_chain() {
+        let (data, bw_data) = (pgf_fixture(), bwfon_fixture());
+        let mut font = Font::try_new(&data, PGFFlags::NONE).unwrap();
+        let abc = Font::try_new_bwfon(&bw_data, &[0x41, 3], PGFFlags::NONE).unwrap();
+        let c = Font::try_new_bwfon(&bw_data, &[0x43, 1], PGFFlags::NONE).unwrap();
+        assert_eq!(font.missing_chars("ACE"), ['C', 'E']);
+        let itself = font.clone();
+        font.set_fallback(&[&abc, &c, &itself]); // a font is not its own fallback
+        assert_eq!(font.missing_chars("ACE"), ['E']);
+
+        // "CC" is laid out by the first font of the chain that has it (16 pixels per char), 'A' and 'B' by the font
+        let options = LayoutOptions { x: 10.0, y: 20.0, column: 0.0, style: render_style(PGFFlags::NONE) };
+        let layout = font.layout("ACCB", &options);
+        let glyphs: Vec<(u8, usize, f32)> = layout.glyphs.iter().filter(|glyph| !glyph.is_shadow())
+            .map(|glyph| (glyph.fallback, glyph.source_index, glyph.rect.0)).collect();
+        assert_eq!(glyphs, [(0, 0, 11.0), (1, 1, 16.0), (1, 2, 32.0), (0, 3, 47.0)]);
+        assert_eq!((layout.end, font.measure("ACCB", None).width), (54.0, 44.0));
+
+        // lines are broken around the run like around the chars of the font
+        let options = LayoutOptions { column: 40.0, ..options };
+        let layout = font.layout("AB CC", &options);
+        assert_eq!(layout.lines.iter().map(|line| line.source.clone()).collect::<Vec<_>>(), vec![0..3, 3..5]);
+
+        // routed chars try their font first
+        font.route_fallback('C'..='C', &c);
+        assert_eq!(font.layout("C", &options).glyphs[0].fallback, 2);
+
+        // the fallback glyphs are drawn first, with the texture of their font
+        let mut backend = RecordingBackend::new();
+        font.print_to(&mut backend, 10.0, 20.0, "AC").unwrap();
+        let textures = backend.commands.iter().filter(|command| matches!(command, RenderCommand::UploadTexture { .. })).count();
+        assert_eq!((textures, backend.draws().count()), (2, 2));
+    }
+
+    #[test]
+    fn glyph_atlas() {
+        // four 6x6 glyphs (7x7 with their gaps) fill a 16x16 texture
+        let mut atlas = Atlas::new(AtlasConfig { page_size: 16, pages: 1 });
+        let places: Vec<(u16, u16)> = (1..5).map(|key| atlas.allocate(key, 6, 6).map(|place| (place.x, place.y)).unwrap()).collect();
+        assert_eq!(places, [(1, 1), (8, 1), (1, 8), (8, 8)]);
+        assert!(atlas.allocate(5, 6, 6).is_none() && atlas.full); // every glyph is used by the current text
+
+        // the least recently used glyph is evicted
+        atlas.begin_text();
+        atlas.hit(1);
+        atlas.hit(3);
+        let place = atlas.allocate(5, 6, 6).unwrap();
+        assert_eq!((place.x, place.y, place.evicted), (8, 1, vec![2]));
+
+        // glyphs used in the current frame are kept, whatever the number of texts
+        atlas.begin_frame();
+        atlas.hit(1);
+        assert_eq!(atlas.allocate(6, 6, 6).unwrap().evicted, [4]);
+        atlas.begin_text();
+        assert_eq!(atlas.allocate(7, 6, 6).unwrap().evicted, [5]);
+        assert_eq!(atlas.allocate(8, 6, 6).unwrap().evicted, [3]);
+        assert!(atlas.allocate(9, 6, 6).is_none());
+        assert_eq!(atlas.stats(), CacheStats { hits: 3, misses: 10, evictions: 4, glyphs: 4, occupancy: 4.0 * 49.0 / 256.0, pages: 1 });
+
+        // fonts count the glyphs drawn from the texture
+        let data = pgf_fixture();
+        let mut font = Font::try_new(&data, PGFFlags::NONE).unwrap();
+        font.print_to(&mut RecordingBackend::new(), 10.0, 20.0, "AB").unwrap();
+        let stats = font.cache_stats();
+        assert_eq!((stats.hits, stats.misses, stats.evictions, stats.glyphs), (1, 3, 0, 3)); // both chars share a shadow
+        font.print_to(&mut RecordingBackend::new(), 10.0, 20.0, "BA").unwrap();
+        assert_eq!((font.cache_stats().hits, font.cache_stats().misses), (5, 3));
+    }
+
+    #[test]
+    fn atlas_pages() {
+        assert_eq!(AtlasConfig::from_options(PGFFlags::CACHE_MED), AtlasConfig::default()); // CACHE_MED is no bit
+        assert_eq!(AtlasConfig::from_options(PGFFlags::CACHE_LARGE), AtlasConfig { page_size: 512, pages: 1 });
+        assert_eq!(AtlasConfig::from_options(PGFFlags::CACHE_ALL).pages, 4);
+        let data = bwfon_fixture();
+        let invalid = AtlasConfig { page_size: 100, pages: 1 };
+        assert_eq!(Font::try_new_bwfon_with_atlas(&data, &[0x41, 3], PGFFlags::NONE, invalid).err(), Some(FontError::InvalidAtlas { page_size: 100, pages: 1 }));
+
+        // a 32x32 page holds one 16x18 glyph: 'A', 'B' and 'C' take a page each (BWFON glyphs have no shadow)
+        let mut font = Font::try_new_bwfon_with_atlas(&data, &[0x41, 3], PGFFlags::NONE, AtlasConfig { page_size: 32, pages: 3 }).unwrap();
+        let options = LayoutOptions { x: 10.0, y: 20.0, column: 0.0, style: render_style(PGFFlags::NONE) };
+        let pages: Vec<(bool, u8)> = font.layout("ABC", &options).glyphs.iter().map(|glyph| (glyph.is_shadow(), glyph.page)).collect();
+        assert_eq!(pages, [(false, 0), (false, 1), (false, 2)]);
+        assert_eq!(font.cache_stats().pages, 3);
+        assert_eq!(font.face().textures[2].data.len(), 32 * 32 / 2); // 4 bits/pixel
+
+        // one draw call per page
+        let mut backend = RecordingBackend::new();
+        font.print_to(&mut backend, 10.0, 20.0, "ABC").unwrap();
+        let textures = backend.commands.iter().filter(|command| matches!(command, RenderCommand::UploadTexture { width: 32, .. })).count();
+        let sizes: Vec<usize> = backend.draws().map(|(_, vertices)| vertices.len()).collect();
+        assert_eq!((textures, sizes), (3, vec![2, 2, 2]));
+
+        // glyphs that do not fit with the others of the frame are left out, not drawn with stale coordinates
+        let mut font = Font::try_new_bwfon_with_atlas(&data, &[0x41, 3], PGFFlags::NONE, AtlasConfig { page_size: 32, pages: 1 }).unwrap();
+        font.begin_frame();
+        let ids: Vec<u16> = font.layout("AB", &options).glyphs.iter().map(|glyph| glyph.glyph_id).collect();
+        assert_eq!(ids, [0]);
+        let mut backend = RecordingBackend::new();
+        font.print_to(&mut backend, 10.0, 20.0, "B").unwrap();
+        let mut cursor = font.cursor_to(&mut backend, 10.0, 40.0);
+        core::fmt::Write::write_str(&mut cursor, "CB").unwrap();
+        cursor.finish().unwrap();
+        assert!(backend.draws().all(|(_, vertices)| vertices.is_empty())); // neither 'B' nor 'C' fit next to 'A'
+        font.begin_frame();
+        assert_eq!(font.layout("B", &options).glyphs.len(), 1); // 'A' can be evicted in the next frame
+    }
+
+    #[test]
+    fn precache() {
+        assert_eq!((charset::latin_1().count(), charset::kana().filter(|c| *c == 'あ' || *c == 'ア').count()), (191, 2));
+        assert_eq!((charset::common_kanji().count(), charset::common_kanji().next()), (2965, Some('亜')));
+
+        // glyphs that are not pre-cached are still cached, into the swizzled page
+        let data = pgf_fixture();
+        let mut font = Font::try_new(&data, PGFFlags::NONE).unwrap();
+        let address = font.face().textures[0].data.as_ptr();
+        assert!(font.precache("AZ".chars()));
+        assert!(font.face().textures[0].swizzled);
+        font.print_to(&mut RecordingBackend::new(), 10.0, 20.0, "AB").unwrap();
+        assert_eq!(font.face().textures[0].data.as_ptr(), address); // draws that are queued still read the page
+        let mut expected = Font::try_new(&data, PGFFlags::NONE).unwrap();
+        expected.print_to(&mut RecordingBackend::new(), 10.0, 20.0, "AB").unwrap();
+        assert!(expected.precache(None));
+        assert_eq!(font.face().textures[0].data.to_vec(), expected.face().textures[0].data.to_vec());
+        // unswizzled, the page is the one glyphs are cached in without pre-caching
+        let mut linear = Font::try_new(&data, PGFFlags::NONE).unwrap();
+        linear.print_to(&mut RecordingBackend::new(), 10.0, 20.0, "AB").unwrap();
+        let mut unswizzled = vec![0; 256 * 256 / 2];
+        unswizzle(TexelFormat::T4, 256, 256, &font.face().textures[0].data, &mut unswizzled).unwrap();
+        assert_eq!(unswizzled, linear.face().textures[0].data.to_vec());
+        let mut backend = RecordingBackend::new();
+        font.print_to(&mut backend, 10.0, 20.0, "A").unwrap();
+        assert_eq!(backend.commands[1], RenderCommand::UploadTexture { width: 256, height: 256, swizzled: true });
+
+        // pre-cached glyphs are never evicted, the others are
+        let data = bwfon_fixture();
+        let atlas = AtlasConfig { page_size: 32, pages: 2 };
+        let mut font = Font::try_new_bwfon_with_atlas(&data, &[0x41, 3], PGFFlags::NONE, AtlasConfig { pages: 1, ..atlas }).unwrap();
+        assert!(!font.precache("AB".chars()) && !font.face().textures[0].swizzled);
+        assert!(font.cache_stats().glyphs == 0 && !font.face().atlas.full); // what fitted was rolled back
+        assert!(font.precache(Some('B')) && font.face().textures[0].swizzled);
+        let mut font = Font::try_new_bwfon_with_atlas(&data, &[0x41, 3], PGFFlags::NONE, atlas).unwrap();
+        assert!(font.precache(Some('A')));
+        font.begin_frame();
+        font.print_to(&mut RecordingBackend::new(), 10.0, 20.0, "B").unwrap();
+        font.begin_frame();
+        let options = LayoutOptions { x: 10.0, y: 20.0, column: 0.0, style: render_style(PGFFlags::NONE) };
+        let pages: Vec<u8> = font.layout("AC", &options).glyphs.iter().map(|glyph| glyph.page).collect();
+        assert_eq!(pages, [0, 1]);
+        assert_eq!((font.cache_stats().evictions, font.cache_stats().pages), (1, 2));
+    }
+
+    #[test]
+    fn swizzle_round_trip() {
+        for (format, width, height) in [(TexelFormat::T4, 64, 16), (TexelFormat::T8, 48, 8), (TexelFormat::Rgba8888, 8, 24)].iter() {
+            let size = *width as usize * format.bits_per_pixel() / 8 * *height as usize;
+            let texture: Vec<u8> = (0..size).map(|i| (i * 7 % 251) as u8).collect();
+            let (mut swizzled, mut unswizzled) = (vec![0; size], vec![0; size]);
+            swizzle(*format, *width, *height, &texture, &mut swizzled).unwrap();
+            assert_ne!(swizzled, texture);
+            unswizzle(*format, *width, *height, &swizzled, &mut unswizzled).unwrap();
+            assert_eq!(unswizzled, texture);
+        }
+
+        // rows of 32 bytes: the second block of a row comes after the 8 rows of the first one
+        let texture: Vec<u8> = (0..=255).collect();
+        let mut swizzled = vec![0; 256];
+        swizzle(TexelFormat::T8, 32, 8, &texture, &mut swizzled).unwrap();
+        assert_eq!((&swizzled[16..20], &swizzled[128..132]), (&[32, 33, 34, 35][..], &[16, 17, 18, 19][..]));
+
+        let invalid = |format, width, height, len| swizzle(format, width, height, &vec![0; len], &mut vec![0; len]);
+        assert_eq!(invalid(TexelFormat::T4, 16, 8, 64), Err(FontError::InvalidTexture { width: 16, height: 8 })); // 8 byte rows
+        assert_eq!(invalid(TexelFormat::T8, 16, 12, 192), Err(FontError::InvalidTexture { width: 16, height: 12 }));
+        assert_eq!(invalid(TexelFormat::Rgba8888, 4, 8, 64), Err(FontError::InvalidTexture { width: 4, height: 8 })); // buffers too small
+        assert_eq!(invalid(TexelFormat::Rgba8888, 4, 8, 128), Ok(()));
+    }
+
+    #[test]
+    fn font_info_and_coverage() {
+        let mut data = pgf_fixture();
+        data[0x24..0x28].copy_from_slice(&(10u32 * 64).to_le_bytes()); // 10 points
+        data[0x30..0x34].copy_from_slice(&(128u32 * 64).to_le_bytes()); // 128 dpi vertically
+        data[0x35..0x35 + 12].copy_from_slice(b"Fixture Sans");
+        data[0x75..0x75 + 4].copy_from_slice(b"Bold");
+        let font = Font::try_new(&data, PGFFlags::NONE).unwrap();
+        let info = font.info();
+        assert_eq!((info.family.as_str(), info.style.as_str(), info.revision), ("Fixture Sans", "Bold", 2));
+        assert_eq!((info.point_size, info.resolution), ((10.0, 0.0), (0.0, 128.0)));
+        assert_eq!((info.max_advance, info.line_height), ((6.0, 8.0), 8.0));
+        assert_eq!((info.glyph_count, info.shadow_count), (3, 1));
+
+        assert!(font.has_char('A') && font.has_char('D'));
+        assert!(!font.has_char('C') && !font.has_char('a') && !font.has_char('😀'));
+        assert_eq!(font.missing_chars("CAB\nDCa😀"), ['C', 'a', '😀']);
+        assert!(font.missing_chars("ABBA\nDAD").is_empty());
+        assert_eq!(font.code_points().collect::<String>(), "ABD");
+
+        let bwfon = Font::try_new_bwfon(bwfon_fixture(), &[0x41, 3], PGFFlags::NONE).unwrap();
+        assert_eq!((bwfon.info().family.as_str(), bwfon.info().glyph_count, bwfon.info().line_height), ("", 3, 18.0));
+        assert_eq!(bwfon.info().shadow_count, 0);
+        assert_eq!(bwfon.code_points().collect::<String>(), "ABC");
+    }
+
+    #[test]
+    fn codepages() {
+        assert_eq!(Codepages::S_JIS.decode_ucs2(&[0x82, 0xA0, 0xB1, b'A']), vec![0x3042, 0xFF71, 'A' as u16]);
+        assert_eq!(Codepages::GBK.decode_ucs2(&[0xB0, 0xA1]), vec![0x554A]);
+        assert_eq!(Codepages::KOREAN.decode_ucs2(&[0xB0, 0xA1]), vec![0xAC00]);
+        assert_eq!(Codepages::BIG5.decode_ucs2(&[0xA4, 0x40]), vec![0x4E00]);
+        assert_eq!(Codepages::US.decode_ucs2(&[0x82]), vec![0xE9]);
+        assert_eq!(Codepages::MLATIN.decode_ucs2(&[0x82]), vec![0xE9]);
+        assert_eq!(Codepages::RUSSIAN.decode_ucs2(&[0x80]), vec![0x0410]);
+        assert_eq!(Codepages::CYRILLIC.decode_ucs2(&[0xC0]), vec![0x0410]);
+        assert_eq!(Codepages::LATIN2.decode_ucs2(&[0x80]), vec![0x20AC]);
+        assert_eq!(Codepages::ASCII.decode_ucs2(&[b'a', 0xE9]), vec!['a' as u16, 0xE9]);
+        assert_eq!(Codepages::UTF8.decode_ucs2("aé".as_bytes()), vec!['a' as u16, 0xE9]);
+        // undefined pairs, lead bytes at the end of the text and invalid UTF-8
+        assert_eq!(Codepages::S_JIS.decode_ucs2(&[0x85, 0x40, b'A', 0x82]), vec![UNDEFINED_CHAR, 'A' as u16, UNDEFINED_CHAR]);
+        assert_eq!(Codepages::UTF8.decode_ucs2(&[0xFF, b'A']), vec![UNDEFINED_CHAR, 'A' as u16]);
+
+        assert_eq!(Codepages::from_options(PGFFlags::STRING_GBK | PGFFlags::ALIGN_CENTER), Codepages::GBK);
+        assert_eq!(Codepages::from_options(PGFFlags::NONE), Codepages::ASCII);
+
+        // a table that is not built in
+        let data = [0x00, 0x01, 0x34, 0x12, 0x00, 0x00, 0x78, 0x56];
+        let table = CodepageTable { high: None, double: Some(DoubleByteTable { leads: (0x90, 0x91), trails: (0x40, 0x41), data: &data }) };
+        let mut output = Vec::new();
+        table.decode_into(&[0x90, 0x41, 0x91, 0x41, 0x91, 0x40, 0x92], &mut output);
+        assert_eq!(output, vec![0x1234, 0x5678, UNDEFINED_CHAR, UNDEFINED_CHAR]);
+    }
+
+    #[test]
+    fn transcoding() {
+        assert_eq!(ccclib::decode(Codepages::S_JIS, &[0x82, 0xA0, 0xB1, b'A', 0x85, 0x40]), "あｱA\u{FFFD}");
+        assert_eq!(ccclib::decode(Codepages::US, &[0x82, 0x9C]), "é£");
+        assert_eq!(ccclib::decode(Codepages::ASCII, &[0xE9]), "é");
+        assert_eq!(ccclib::decode(Codepages::UTF8, &[b'a', 0xFF]), "a\u{FFFD}");
+
+        for (codepage, text) in [(Codepages::S_JIS, "セーブデータ ｾｰﾌﾞ"), (Codepages::GBK, "存档"), (Codepages::KOREAN, "저장"), (Codepages::BIG5, "存檔"),
+                                 (Codepages::US, "Éé£"), (Codepages::MLATIN, "ÆØ"), (Codepages::RUSSIAN, "Привет"), (Codepages::CYRILLIC, "Привет"),
+                                 (Codepages::LATIN2, "€é"), (Codepages::UTF8, "é😀")].iter() {
+            let bytes = ccclib::encode(*codepage, text).unwrap();
+            assert_eq!(ccclib::decode(*codepage, &bytes), *text);
+        }
+        assert_eq!(ccclib::encode(Codepages::S_JIS, "あA").unwrap(), vec![0x82, 0xA0, b'A']);
+        // chars with several codes use the first one
+        assert_eq!(ccclib::encode(Codepages::S_JIS, "\u{FFE2}").unwrap(), vec![0x81, 0xCA]);
+        assert_eq!(ccclib::encode(Codepages::US, "aЖ"), Err(Unmappable { ch: 'Ж', index: 1 }));
+        assert_eq!(ccclib::encode(Codepages::ASCII, "é").unwrap(), vec![0xE9]);
+    }
+
+    #[test]
+    fn streaming_decoder() {
+        let mut decoder = Decoder::new(Codepages::S_JIS);
+        let mut output = String::new();
+        // a char split between two pieces
+        decoder.decode(&[b'A', 0x82], &mut output);
+        assert_eq!(output, "A");
+        decoder.decode(&[0xA0, 0x82], &mut output);
+        decoder.finish(&mut output);
+        assert_eq!(output, "Aあ\u{FFFD}");
+
+        let mut decoder = Decoder::new(Codepages::UTF8);
+        let mut output = String::new();
+        let bytes = "aé€".as_bytes();
+        for byte in bytes.iter() {
+            decoder.decode(core::slice::from_ref(byte), &mut output);
+        }
+        decoder.finish(&mut output);
+        assert_eq!(output, "aé€");
+    }
+
+    #[test]
+    fn utf8_units() {
+        let data = pgf_fixture();
+        let mut font = Font::try_new(&data, PGFFlags::NONE).unwrap();
+        let units = |font: &Font, bytes: &[u8]| font.encode(bytes).collect::<Vec<u16>>();
+        assert_eq!(units(&font, "Aé€".as_bytes()), vec!['A' as u16, 0xE9, 0x20AC]);
+        // 4 byte sequences, truncated sequences, stray continuation bytes, overlong sequences and surrogates
+        assert_eq!(units(&font, "A😀B".as_bytes()), vec!['A' as u16, '?' as u16, 'B' as u16]);
+        assert_eq!(units(&font, &[b'A', 0xE2, 0x82]), vec!['A' as u16, '?' as u16]);
+        assert_eq!(units(&font, &[0xE2, 0x82, b'A']), vec!['?' as u16, 'A' as u16]);
+        assert_eq!(units(&font, &[0x80, 0xC0, 0x80, 0xED, 0xA0, 0x80]), vec!['?' as u16; 3]);
+        assert_eq!(units(&font, &[0xF0, 0x9F]), vec!['?' as u16]);
+
+        font.set_replacement_char(0xFFFD);
+        assert_eq!(units(&font, "😀".as_bytes()), vec![0xFFFD]);
+        font.set_utf8_surrogates(true);
+        assert_eq!(units(&font, "😀A".as_bytes()), vec![0xD83D, 0xDE00, 'A' as u16]);
+
+        let mut encoder = font.encode("é😀A".as_bytes());
+        let mut offsets = Vec::new();
+        while let (offset, Some(_)) = (encoder.offset(), encoder.next()) {
+            offsets.push(offset);
+        }
+        assert_eq!(offsets, vec![0, 2, 2, 6]);
+    }
+
+    #[test]
+    fn print_bytes() {
+        let data = pgf_fixture();
+        let mut font = Font::try_new(&data, PGFFlags::NONE).unwrap();
+        font.set_style(FontStyle { options: PGFFlags::STRING_S_JIS, ..render_style(PGFFlags::NONE) });
+        let mut backend = RecordingBackend::new();
+        // 'A', a kana that is not in the font, 'B', and everything after the NUL byte is ignored
+        assert_eq!(font.print_bytes_to(&mut backend, 0.0, 10.0, &[b'A', 0x82, 0xA0, b'B', 0, b'A']), Ok(12.0));
+        assert_eq!(backend.draws().next().unwrap().1.len(), 8);
+    }
+
+    #[test]
+    fn soft_cos() {
+        let cases = [(0.0, 1.0), (PI / 3.0, 0.5), (PI / 2.0, 0.0), (PI, -1.0), (-PI / 3.0, 0.5),
+            (2.0 * PI, 1.0), (7.0 * PI / 3.0, 0.5), (-10.0 * PI, 1.0), (4.0, -0.653_643_6)];
+        for (rad, cos) in cases.iter() {
+            assert!((soft_cosf(*rad) - cos).abs() < 1e-5, "cos({}) = {}", rad, soft_cosf(*rad));
+        }
+    }
+
+    /// A charmap shaped like the one of a Japanese PGF font: Latin, Cyrillic, punctuation, kana,
+    /// all the CJK ideographs and fullwidth forms, with glyph ids in a different order than the chars
+    /// and some chars of the table missing.
+    fn cjk_charmap() -> (Vec<u16>, Vec<u16>) {
+        let table = vec![0x0020, 0x0060, 0x00A0, 0x00E0, 0x0401, 0x0050, 0x2010, 0x0100, 0x3000, 0x0100, 0x4E00, 0x51A6, 0xFF01, 0x00EF];
+        let len = table.chunks_exact(2).map(|pair| pair[1] as usize).sum::<usize>();
+        let charmap = (0..len).map(|i| if i % 16 == 15 { NO_CHAR } else { (len - 1 - i) as u16 }).collect();
+        (table, charmap)
+    }
+
+    /// A charmap shaped like the one of the firmware jpn0.pgf: a single `(0x20, 0xFFC6)` pair covering
+    /// 0x0020..=0xFFE5, where the chars of the font (ASCII, Latin-1, Greek, Cyrillic, symbols, box drawing,
+    /// kana, about 6300 CJK ideographs spread over the whole block and fullwidth forms) have glyph ids in
+    /// the order of the chars and every other char is missing.
+    fn jpn0_charmap() -> (Vec<u16>, Vec<u16>) {
+        let table = vec![0x0020, 0xFFC6];
+        let ranges = [(0x0020, 0x007E), (0x00A1, 0x00FF), (0x0391, 0x03C9), (0x0401, 0x0451), (0x2010, 0x203B),
+            (0x2190, 0x2199), (0x2200, 0x22A5), (0x2460, 0x2473), (0x2500, 0x254B), (0x25A0, 0x25EF), (0x3000, 0x30FF)];
+        let mut next = 0u16;
+        let mut id = |present: bool| if present { next += 1; next - 1 } else { NO_CHAR };
+        let charmap = (0x0020..=0xFFE5u32).map(|ucs2| match ucs2 {
+            0x4E00..=0x9FA5 => id(ucs2.wrapping_mul(2_654_435_761) >> 16 & 0xFF < 78), // about 30% of the block
+            0xFF01..=0xFFE5 => id(ucs2 <= 0xFF9F || ucs2 >= 0xFFE0),
+            _ => id(ranges.iter().any(|(first, last)| (*first..=*last).contains(&ucs2))),
+        }).collect();
+        (table, charmap)
+    }
+
+    #[test]
+    fn char_lookup() {
+        let (table, charmap) = cjk_charmap();
+        let lookup = CharLookup::new(&table, &charmap);
+        assert!(matches!(lookup, CharLookup::Pages { .. }));
+        for ucs2 in 0..=u16::MAX {
+            assert_eq!(lookup.get(ucs2), CharLookup::scan(&table, &charmap, ucs2), "{:#06x}", ucs2);
+        }
+        assert_eq!(lookup.get(0x4E00), charmap[0x60 + 0xE0 + 0x50 + 0x100 + 0x100]);
+
+        // BWFON ids are numbered in the order of the table
+        let lookup = CharLookup::new(&table, &[]);
+        assert_eq!((lookup.get(0x0020), lookup.get(0x00A0), lookup.get(0x001F), lookup.get(0xFFFF)), (0, 0x60, NO_CHAR, NO_CHAR));
+
+        // the jpn0.pgf charmap has a single pair and only uses some pages
+        let (table, charmap) = jpn0_charmap();
+        let lookup = CharLookup::new(&table, &charmap);
+        assert!(matches!(lookup, CharLookup::Pages { .. }));
+        for ucs2 in 0..=u16::MAX {
+            assert_eq!(lookup.get(ucs2), CharLookup::scan(&table, &charmap, ucs2), "{:#06x}", ucs2);
+        }
+        assert_eq!((lookup.get(0x0020), lookup.get(0x0080), lookup.get(0xFFE5)), (0, NO_CHAR, charmap[0xFFE5 - 0x20]));
+
+        // a table with every char is stored directly
+        let lookup = CharLookup::new(&[0x0000, 0xFFFF], &[]);
+        assert!(matches!(lookup, CharLookup::Direct(_)));
+        assert_eq!((lookup.get(0x1234), lookup.get(0xFFFF)), (0x1234, NO_CHAR));
+    }
+
+    #[bench]
+    fn bench_char_scan(b: &mut test::Bencher) {
+        let (table, charmap) = jpn0_charmap();
+        b.iter(|| (0..=u16::MAX).map(|ucs2| CharLookup::scan(&table, &charmap, test::black_box(ucs2)) as u32).sum::<u32>());
+    }
+
+    #[bench]
+    fn bench_char_lookup(b: &mut test::Bencher) {
+        let (table, charmap) = jpn0_charmap();
+        let lookup = CharLookup::new(&table, &charmap);
+        b.iter(|| (0..=u16::MAX).map(|ucs2| lookup.get(test::black_box(ucs2)) as u32).sum::<u32>());
+    }
+
+    #[bench]
+    fn bench_char_lookup_new(b: &mut test::Bencher) {
+        let (table, charmap) = jpn0_charmap();
+        b.iter(|| CharLookup::new(test::black_box(&table), &charmap));
+    }
 }